@@ -0,0 +1,497 @@
+use crate::args::Easing;
+use crate::send;
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+use vtubestudio::data::{
+    InjectParameterDataMode, InjectParameterDataRequest, InjectParameterDataResponse,
+    InputParameterListRequest, ParameterValue,
+};
+use vtubestudio::Client;
+
+/// Parameter keyframes for `vts params play`, loaded from either a CSV file:
+///
+/// ```csv
+/// time,FaceAngleX,MouthOpen
+/// 0,0,0
+/// 0.5,30,
+/// 1.5,-30,1
+/// ```
+///
+/// or a JSON file:
+///
+/// ```json
+/// [
+///   {"time": 0, "values": {"FaceAngleX": 0, "MouthOpen": 0}},
+///   {"time": 0.5, "values": {"FaceAngleX": 30}},
+///   {"time": 1.5, "values": {"FaceAngleX": -30, "MouthOpen": 1}}
+/// ]
+/// ```
+///
+/// Times are in seconds from the start of the animation. A parameter missing from a keyframe (or
+/// an empty CSV cell) is interpolated between the keyframes around it.
+#[derive(Debug)]
+pub struct Animation {
+    /// Keyframes (time and value) for each parameter ID, sorted by time.
+    tracks: BTreeMap<String, Vec<(f64, f64)>>,
+    duration: f64,
+}
+
+/// Values of parameters at a time (in seconds). Also used for each line written by
+/// `vts params record`.
+#[derive(Debug, Serialize, Deserialize)]
+struct Keyframe {
+    time: f64,
+    values: BTreeMap<String, f64>,
+}
+
+impl Animation {
+    /// Loads keyframes from a file, as CSV if the extension is `.csv`, otherwise as JSON.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents =
+            std::fs::read_to_string(path).with_context(|| format!("failed to read {:?}", path))?;
+
+        let is_csv = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"));
+        let keyframes = if is_csv {
+            parse_csv(&contents)
+        } else {
+            parse_json(&contents)
+        }
+        .with_context(|| format!("failed to parse animation {:?}", path))?;
+
+        Self::from_keyframes(keyframes)
+    }
+
+    fn from_keyframes(keyframes: Vec<(String, f64, f64)>) -> Result<Self> {
+        let mut tracks: BTreeMap<String, Vec<(f64, f64)>> = BTreeMap::new();
+        let mut duration: f64 = 0.0;
+
+        for (id, time, value) in keyframes {
+            if !time.is_finite() || time < 0.0 {
+                bail!("invalid keyframe time `{}` for `{}`", time, id);
+            }
+            duration = duration.max(time);
+            tracks.entry(id).or_default().push((time, value));
+        }
+
+        if tracks.is_empty() {
+            bail!("animation has no keyframes");
+        }
+
+        for keyframes in tracks.values_mut() {
+            keyframes.sort_by(|a, b| a.0.total_cmp(&b.0));
+        }
+
+        Ok(Self { tracks, duration })
+    }
+
+    /// Values of every parameter at a time (in seconds). Before the first keyframe (or after the
+    /// last one) of a parameter, its value is that keyframe's value.
+    pub fn values_at(&self, time: f64, easing: Easing) -> Vec<ParameterValue> {
+        self.tracks
+            .iter()
+            .map(|(id, keyframes)| {
+                let next = keyframes.partition_point(|(t, _)| *t <= time);
+                let value = match (keyframes.get(next.wrapping_sub(1)), keyframes.get(next)) {
+                    (Some(&(t0, v0)), Some(&(t1, v1))) => {
+                        v0 + (v1 - v0) * easing.apply((time - t0) / (t1 - t0))
+                    }
+                    (Some(&(_, value)), None) | (None, Some(&(_, value))) => value,
+                    (None, None) => unreachable!("tracks always have keyframes"),
+                };
+
+                ParameterValue {
+                    id: id.clone(),
+                    value,
+                    weight: None,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Parses a JSON array of keyframes, each with a `time` and a map of parameter values.
+fn parse_json(contents: &str) -> Result<Vec<(String, f64, f64)>> {
+    let keyframes = serde_json::from_str::<Vec<Keyframe>>(contents)
+        .context("expected an array of `{\"time\": ..., \"values\": {...}}` objects")?;
+
+    Ok(keyframes
+        .into_iter()
+        .flat_map(|keyframe| {
+            let time = keyframe.time;
+            keyframe
+                .values
+                .into_iter()
+                .map(move |(id, value)| (id, time, value))
+        })
+        .collect())
+}
+
+/// Parses CSV with a `time` column followed by one column per parameter ID.
+fn parse_csv(contents: &str) -> Result<Vec<(String, f64, f64)>> {
+    let mut lines = contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty());
+
+    let (_, header) = lines.next().context("missing header row")?;
+    let mut columns = header.split(',').map(str::trim);
+    if !columns
+        .next()
+        .is_some_and(|column| column.eq_ignore_ascii_case("time"))
+    {
+        bail!("the first column should be `time`");
+    }
+    let ids: Vec<&str> = columns.collect();
+
+    let mut keyframes = Vec::new();
+    for (index, line) in lines {
+        let parse = |cell: &str| -> Result<f64> {
+            cell.parse().with_context(|| {
+                format!(
+                    "could not parse `{}` as a number on line {}",
+                    cell,
+                    index + 1
+                )
+            })
+        };
+
+        let mut cells = line.split(',').map(str::trim);
+        let time = parse(cells.next().unwrap_or_default())?;
+        for (id, cell) in ids.iter().zip(cells) {
+            if !cell.is_empty() {
+                keyframes.push((id.to_string(), time, parse(cell)?));
+            }
+        }
+    }
+
+    Ok(keyframes)
+}
+
+/// Injects the animation in real time at `fps` values per second, ending with the values of the
+/// last keyframe. Stops early on Ctrl-C. Returns the response to the last request.
+pub async fn play(
+    client: &mut Client,
+    animation: &Animation,
+    easing: Easing,
+    fps: f64,
+    face_found: bool,
+) -> Result<Option<InjectParameterDataResponse>> {
+    if fps.is_nan() || fps <= 0.0 {
+        bail!("`--fps` should be greater than 0");
+    }
+
+    let started = tokio::time::Instant::now();
+    let mut ticker = tokio::time::interval(Duration::from_secs_f64(1.0 / fps));
+
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+
+    let mut resp = None;
+    loop {
+        tokio::select! {
+            _ = &mut ctrl_c => return Ok(resp),
+            _ = ticker.tick() => {
+                let time = started.elapsed().as_secs_f64().min(animation.duration);
+                let req = InjectParameterDataRequest {
+                    face_found,
+                    mode: Some(InjectParameterDataMode::Set.into()),
+                    parameter_values: animation.values_at(time, easing),
+                };
+                resp = Some(send(client, &req).await?);
+
+                if time >= animation.duration {
+                    return Ok(resp);
+                }
+            }
+        }
+    }
+}
+
+/// Polls the input parameters `rate` times per second, writing each sample as a line of JSON
+/// (e.g., `{"time": 0.5, "values": {"FaceAngleX": 12.3}}`) to the file. Only the given parameters
+/// are recorded, or all of them if there are none. Stops after `duration` (if given) or on
+/// Ctrl-C, and returns a summary of the recording.
+pub async fn record(
+    client: &mut Client,
+    path: &Path,
+    rate: f64,
+    params: &[String],
+    duration: Option<Duration>,
+) -> Result<Value> {
+    if rate.is_nan() || rate <= 0.0 {
+        bail!("`--rate` should be greater than 0");
+    }
+
+    let file = File::create(path).with_context(|| format!("failed to create {:?}", path))?;
+    let mut out = BufWriter::new(file);
+
+    let started = tokio::time::Instant::now();
+    let deadline = duration.map(|duration| started + duration);
+    let mut ticker = tokio::time::interval(Duration::from_secs_f64(1.0 / rate));
+    let mut samples = 0;
+
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+
+    loop {
+        tokio::select! {
+            _ = crate::events::sleep_until(deadline) => break,
+            _ = &mut ctrl_c => break,
+            _ = ticker.tick() => {
+                let time = started.elapsed().as_secs_f64();
+                let resp = send(client, &InputParameterListRequest {}).await?;
+
+                let values = resp
+                    .default_parameters
+                    .into_iter()
+                    .chain(resp.custom_parameters)
+                    .filter(|param| params.is_empty() || params.contains(&param.name))
+                    .map(|param| (param.name, param.value))
+                    .collect();
+
+                serde_json::to_writer(&mut out, &Keyframe { time, values })?;
+                writeln!(out)?;
+                samples += 1;
+            }
+        }
+    }
+
+    out.flush()
+        .with_context(|| format!("failed to write to {:?}", path))?;
+
+    Ok(json!({
+        "file": path,
+        "samples": samples,
+        "seconds": started.elapsed().as_secs_f64(),
+    }))
+}
+
+/// How often values are sent while fading out a stopped replay.
+const FADE_OUT_FPS: f64 = 30.0;
+
+/// Loads the samples written by `vts params record`, sorted by time.
+fn load_recording(path: &Path) -> Result<Vec<Keyframe>> {
+    let contents =
+        std::fs::read_to_string(path).with_context(|| format!("failed to read {:?}", path))?;
+
+    let mut samples = contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str::<Keyframe>(line)
+                .with_context(|| format!("invalid sample on line {} of {:?}", index + 1, path))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    if samples.is_empty() {
+        bail!("recording {:?} has no samples", path);
+    }
+
+    samples.sort_by(|a, b| a.time.total_cmp(&b.time));
+    Ok(samples)
+}
+
+/// Weight of injected values, going from 0 to 1 within `ramp` seconds of the start or end of a
+/// replay, so that the replay blends with live tracking instead of jumping to it.
+fn ramp_weight(from_edge: f64, ramp: f64) -> f64 {
+    if ramp > 0.0 {
+        (from_edge / ramp).clamp(0.0, 1.0)
+    } else {
+        1.0
+    }
+}
+
+fn sample_request(sample: &Keyframe, weight: f64, face_found: bool) -> InjectParameterDataRequest {
+    InjectParameterDataRequest {
+        face_found,
+        mode: Some(InjectParameterDataMode::Set.into()),
+        parameter_values: sample
+            .values
+            .iter()
+            .map(|(id, value)| ParameterValue {
+                id: id.clone(),
+                value: *value,
+                weight: Some(weight),
+            })
+            .collect(),
+    }
+}
+
+/// Re-injects samples written by `vts params record` with their original timing (scaled by
+/// `speed`), ramping the weight in and out over `ramp`. With `looping`, the recording repeats
+/// until Ctrl-C is pressed. Stopping with Ctrl-C fades out from the last sample. Returns a
+/// summary of the replay.
+pub async fn replay(
+    client: &mut Client,
+    path: &Path,
+    looping: bool,
+    speed: f64,
+    ramp: Duration,
+    face_found: bool,
+) -> Result<Value> {
+    if !speed.is_finite() || speed <= 0.0 {
+        bail!("`--speed` should be greater than 0");
+    }
+
+    let samples = load_recording(path)?;
+    let first_time = samples[0].time;
+    let length = (samples[samples.len() - 1].time - first_time) / speed;
+    if looping && length <= 0.0 {
+        bail!("recording {:?} is too short to loop", path);
+    }
+
+    let ramp = ramp.as_secs_f64();
+    let started = tokio::time::Instant::now();
+    let mut sent = 0;
+    let mut loops = 0;
+    let mut last = None;
+
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+
+    'replay: loop {
+        let loop_started = tokio::time::Instant::now();
+
+        for sample in &samples {
+            let offset = (sample.time - first_time) / speed;
+            tokio::select! {
+                _ = &mut ctrl_c => break 'replay,
+                _ = tokio::time::sleep_until(loop_started + Duration::from_secs_f64(offset)) => {}
+            }
+
+            let from_start = started.elapsed().as_secs_f64();
+            let from_end = if looping {
+                f64::INFINITY
+            } else {
+                length - offset
+            };
+            let weight = ramp_weight(from_start.min(from_end), ramp);
+
+            send(client, &sample_request(sample, weight, face_found)).await?;
+            last = Some((sample, weight));
+            sent += 1;
+        }
+
+        loops += 1;
+        if !looping {
+            break;
+        }
+    }
+
+    // When stopped partway through, fade out from the last sample rather than letting the values
+    // snap back to live tracking
+    if let Some((sample, weight)) = last {
+        let steps = (ramp * weight * FADE_OUT_FPS).ceil() as u64;
+        let mut ticker = tokio::time::interval(Duration::from_secs_f64(1.0 / FADE_OUT_FPS));
+        for step in 1..=steps {
+            ticker.tick().await;
+            let faded = weight * (1.0 - step as f64 / steps as f64);
+            send(client, &sample_request(sample, faded, face_found)).await?;
+        }
+    }
+
+    Ok(json!({
+        "file": path,
+        "samples": sent,
+        "loops": loops,
+        "seconds": started.elapsed().as_secs_f64(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CSV: &str = "time,FaceAngleX,MouthOpen\n0,0,0\n0.5,30,\n\n1.5,-30,1\n";
+
+    const JSON: &str = r#"[
+        {"time": 0, "values": {"FaceAngleX": 0, "MouthOpen": 0}},
+        {"time": 0.5, "values": {"FaceAngleX": 30}},
+        {"time": 1.5, "values": {"FaceAngleX": -30, "MouthOpen": 1}}
+    ]"#;
+
+    fn values(animation: &Animation, time: f64, easing: Easing) -> Vec<(String, f64)> {
+        animation
+            .values_at(time, easing)
+            .into_iter()
+            .map(|value| (value.id, value.value))
+            .collect()
+    }
+
+    fn pairs(values: &[(&str, f64)]) -> Vec<(String, f64)> {
+        values.iter().map(|(id, v)| (id.to_string(), *v)).collect()
+    }
+
+    #[test]
+    fn csv_and_json_are_equivalent() {
+        let mut csv = parse_csv(CSV).unwrap();
+        let mut json = parse_json(JSON).unwrap();
+        csv.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        json.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        assert_eq!(csv, json);
+        assert_eq!(csv.len(), 5);
+    }
+
+    #[test]
+    fn invalid_keyframes() {
+        assert!(parse_csv("").is_err());
+        assert!(parse_csv("seconds,FaceAngleX\n0,0").is_err());
+        assert!(parse_csv("time,FaceAngleX\n0,abc").is_err());
+        assert!(Animation::from_keyframes(Vec::new()).is_err());
+        assert!(Animation::from_keyframes(vec![("FaceAngleX".to_owned(), -1.0, 0.0)]).is_err());
+    }
+
+    #[test]
+    fn interpolation() {
+        let animation = Animation::from_keyframes(parse_csv(CSV).unwrap()).unwrap();
+        assert_eq!(animation.duration, 1.5);
+
+        let linear = |time| values(&animation, time, Easing::Linear);
+        assert_eq!(
+            linear(0.0),
+            pairs(&[("FaceAngleX", 0.0), ("MouthOpen", 0.0)])
+        );
+        assert_eq!(
+            linear(0.25),
+            pairs(&[("FaceAngleX", 15.0), ("MouthOpen", 0.25 / 1.5)])
+        );
+        // MouthOpen has no keyframe at 0.5, so it's interpolated from 0 to 1.5
+        assert_eq!(
+            linear(1.0),
+            pairs(&[("FaceAngleX", 0.0), ("MouthOpen", 1.0 / 1.5)])
+        );
+        // Values hold after the last keyframe
+        assert_eq!(
+            linear(2.0),
+            pairs(&[("FaceAngleX", -30.0), ("MouthOpen", 1.0)])
+        );
+
+        let eased = values(&animation, 0.25, Easing::EaseIn);
+        assert_eq!(eased[0], ("FaceAngleX".to_owned(), 30.0 * 0.125));
+    }
+
+    #[test]
+    fn unsorted_keyframes() {
+        let animation = Animation::from_keyframes(
+            parse_json(r#"[{"time": 1, "values": {"A": 10}}, {"time": 0, "values": {"A": 0}}]"#)
+                .unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            values(&animation, 0.5, Easing::Linear),
+            pairs(&[("A", 5.0)])
+        );
+    }
+}
@@ -0,0 +1,109 @@
+use anyhow::{bail, Result};
+use futures_util::stream::{self, StreamExt};
+use serde::Serialize;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use vtubestudio::data::{ApiStateRequest, StatisticsRequest};
+use vtubestudio::Client;
+
+/// Request sent by `vts bench`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BenchRequest {
+    State,
+    Stats,
+}
+
+impl BenchRequest {
+    pub fn variants() -> &'static [&'static str] {
+        &["state", "stats"]
+    }
+}
+
+impl FromStr for BenchRequest {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Ok(match value {
+            "state" => Self::State,
+            "stats" => Self::Stats,
+            other => bail!(
+                "Unknown value `{}`. Should be one of `state` or `stats`.",
+                other
+            ),
+        })
+    }
+}
+
+/// Round-trip latencies (in milliseconds) of the successful requests.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BenchResult {
+    pub count: usize,
+    pub concurrency: usize,
+    pub failures: usize,
+    pub total_ms: f64,
+    pub requests_per_second: f64,
+    pub min_ms: Option<f64>,
+    pub avg_ms: Option<f64>,
+    pub p50_ms: Option<f64>,
+    pub p95_ms: Option<f64>,
+    pub max_ms: Option<f64>,
+}
+
+/// Sends `count` requests, with up to `concurrency` of them in flight at once, and measures the
+/// round-trip latency of each.
+pub async fn run(
+    client: &Client,
+    request: BenchRequest,
+    count: usize,
+    concurrency: usize,
+) -> Result<BenchResult> {
+    if concurrency == 0 {
+        bail!("concurrency should be at least 1");
+    }
+
+    let started = Instant::now();
+    let results: Vec<Result<Duration>> = stream::iter(0..count)
+        .map(|_| {
+            let mut client = client.clone();
+            async move {
+                let sent = Instant::now();
+                match request {
+                    BenchRequest::State => drop(client.send(&ApiStateRequest {}).await?),
+                    BenchRequest::Stats => drop(client.send(&StatisticsRequest {}).await?),
+                }
+                Ok(sent.elapsed())
+            }
+        })
+        .buffer_unordered(concurrency)
+        .collect()
+        .await;
+    let total = started.elapsed();
+
+    let failures = results.iter().filter(|result| result.is_err()).count();
+    let mut latencies: Vec<f64> = results
+        .into_iter()
+        .filter_map(Result::ok)
+        .map(|latency| latency.as_secs_f64() * 1000.0)
+        .collect();
+    latencies.sort_by(f64::total_cmp);
+
+    let percentile = |p: f64| {
+        let rank = (p * latencies.len() as f64).ceil() as usize;
+        latencies.get(rank.saturating_sub(1)).copied()
+    };
+
+    Ok(BenchResult {
+        count,
+        concurrency,
+        failures,
+        total_ms: total.as_secs_f64() * 1000.0,
+        requests_per_second: count as f64 / total.as_secs_f64(),
+        min_ms: latencies.first().copied(),
+        avg_ms: (!latencies.is_empty())
+            .then(|| latencies.iter().sum::<f64>() / latencies.len() as f64),
+        p50_ms: percentile(0.5),
+        p95_ms: percentile(0.95),
+        max_ms: latencies.last().copied(),
+    })
+}
@@ -0,0 +1,102 @@
+use anyhow::{Context, Result};
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+/// How long cached lists are used before being fetched again.
+const TTL_SECONDS: i64 = 5 * 60;
+
+/// Location of the cache file and the URL of the VTube Studio instance, if caching is enabled for
+/// this run.
+static CACHE: OnceCell<(PathBuf, String)> = OnceCell::new();
+
+/// Cached name-to-ID maps, keyed by URL and then by list (e.g., `models`).
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CacheFile {
+    #[serde(flatten)]
+    hosts: BTreeMap<String, BTreeMap<String, Entry>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Entry {
+    fetched_at: i64,
+    ids: HashMap<String, String>,
+}
+
+/// Enables the name cache for a VTube Studio instance. Until this is called, lookups always miss
+/// and nothing is written.
+pub fn init(url: &str) {
+    if let Some(dirs) = directories::ProjectDirs::from("com.github", "walfie", "vtubestudio-cli") {
+        let path = dirs.cache_dir().join("names.json");
+        let _ = CACHE.set((path, url.to_owned()));
+    }
+}
+
+/// Looks up the ID for a name in a cached list, if the list was fetched recently.
+pub fn get(list: &str, name: &str) -> Option<String> {
+    let (path, host) = CACHE.get()?;
+    let now = chrono::Utc::now().timestamp();
+
+    read(path)
+        .ok()?
+        .hosts
+        .get(host)?
+        .get(list)
+        .filter(|entry| now - entry.fetched_at < TTL_SECONDS)?
+        .ids
+        .get(name)
+        .cloned()
+}
+
+/// Replaces a cached list with freshly fetched `(name, ID)` pairs.
+pub fn put<'a>(list: &str, ids: impl IntoIterator<Item = (&'a String, &'a String)>) {
+    let entry = Entry {
+        fetched_at: chrono::Utc::now().timestamp(),
+        ids: ids
+            .into_iter()
+            .map(|(name, id)| (name.clone(), id.clone()))
+            .collect(),
+    };
+
+    update(|lists| {
+        lists.insert(list.to_owned(), entry);
+    });
+}
+
+/// Removes a cached list, e.g., after a cached ID turned out to be stale.
+pub fn invalidate(list: &str) {
+    update(|lists| {
+        lists.remove(list);
+    });
+}
+
+fn update(f: impl FnOnce(&mut BTreeMap<String, Entry>)) {
+    let (path, host) = match CACHE.get() {
+        Some(cache) => cache,
+        None => return,
+    };
+
+    let result = (|| {
+        let mut file = read(path).unwrap_or_default();
+        f(file.hosts.entry(host.clone()).or_default());
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, serde_json::to_vec(&file)?)
+            .with_context(|| format!("failed to write {:?}", path))
+    })();
+
+    // The cache is only an optimization, so failing to write it isn't an error
+    if let Err(e) = result {
+        debug!(error = %e, "Failed to update name cache");
+    }
+}
+
+fn read(path: &Path) -> Result<CacheFile> {
+    let contents = std::fs::read(path)?;
+    Ok(serde_json::from_slice(&contents)?)
+}
@@ -0,0 +1,601 @@
+use crate::args::{Config, ConfigFormat, PluginToken};
+use crate::crypto;
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::{debug, error};
+use vtubestudio::data::AuthenticationRequest;
+use vtubestudio::Client;
+
+/// Current version of the config file layout. Config files with an older version are migrated
+/// when they're read (see `migrate`).
+const CURRENT_VERSION: u64 = 1;
+
+/// Contents of the config file. The settings for the default profile are stored at the top
+/// level, and any named profiles are stored under `profiles`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ConfigFile {
+    pub version: u64,
+    #[serde(flatten)]
+    pub default: Config,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, Config>,
+}
+
+impl ConfigFile {
+    pub fn new(default: Config) -> Self {
+        Self {
+            version: CURRENT_VERSION,
+            default,
+            profiles: BTreeMap::new(),
+        }
+    }
+
+    pub fn read(config_path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(config_path).with_context(|| {
+            let bin = std::env::current_exe().unwrap_or_else(|_| PathBuf::from("vts"));
+            format!(
+                "failed to load config file from {:?} (try running `{:?} init` to create the file)",
+                config_path, bin
+            )
+        })?;
+
+        let mut value: Value =
+            match ConfigFormat::from_path(config_path) {
+                ConfigFormat::Json => serde_json::from_str(&contents)
+                    .context("failed to parse JSON from config file")?,
+                ConfigFormat::Toml => {
+                    toml::from_str(&contents).context("failed to parse TOML from config file")?
+                }
+                ConfigFormat::Yaml => serde_yaml::from_str(&contents)
+                    .context("failed to parse YAML from config file")?,
+            };
+
+        migrate(&mut value)?;
+        serde_json::from_value(value).context("invalid config file")
+    }
+
+    pub fn write(&self, config_path: &Path) -> Result<()> {
+        if let Some(base_path) = config_path.parent() {
+            std::fs::create_dir_all(base_path)
+                .with_context(|| format!("Failed to create directory {:?}", base_path))?;
+        }
+
+        let contents = match ConfigFormat::from_path(config_path) {
+            ConfigFormat::Json => serde_json::to_string_pretty(self)?,
+            ConfigFormat::Toml => toml::to_string_pretty(self)?,
+            ConfigFormat::Yaml => serde_yaml::to_string(self)?,
+        };
+
+        if let Err(e) = std::fs::write(config_path, contents) {
+            error!(?config_path, "Failed to write config file");
+            anyhow::bail!(e);
+        }
+
+        Ok(())
+    }
+
+    /// Returns a copy of the config file with the tokens in every profile encrypted (if enabled).
+    pub fn encrypted(&self) -> Result<Self> {
+        self.try_map_profiles(Config::encrypted)
+    }
+
+    /// Returns a copy of the config file with the tokens in every profile decrypted.
+    pub fn decrypted(&self) -> Result<Self> {
+        self.try_map_profiles(Config::decrypted)
+    }
+
+    fn try_map_profiles(&self, f: impl Fn(&Config) -> Result<Config>) -> Result<Self> {
+        Ok(Self {
+            version: self.version,
+            default: f(&self.default)?,
+            profiles: self
+                .profiles
+                .iter()
+                .map(|(name, conf)| Ok((name.clone(), f(conf)?)))
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    /// Gets the config for the named profile, or the default profile if `None`.
+    pub fn profile(&self, name: Option<&str>) -> Result<&Config> {
+        match name {
+            None => Ok(&self.default),
+            Some(name) => self.profiles.get(name).with_context(|| {
+                format!(
+                    "no profile named `{}` in config file (try running `vts --profile {} config init`)",
+                    name, name
+                )
+            }),
+        }
+    }
+
+    pub fn profile_mut(&mut self, name: Option<&str>) -> Result<&mut Config> {
+        match name {
+            None => Ok(&mut self.default),
+            Some(name) => self
+                .profiles
+                .get_mut(name)
+                .with_context(|| format!("no profile named `{}` in config file", name)),
+        }
+    }
+
+    /// Sets the config for the named profile (or the default profile), creating it if needed.
+    pub fn set_profile(&mut self, name: Option<&str>, conf: Config) {
+        match name {
+            None => self.default = conf,
+            Some(name) => {
+                self.profiles.insert(name.to_owned(), conf);
+            }
+        }
+    }
+}
+
+impl Config {
+    /// Keys that can be used with `config get`, `config set`, and `config unset`.
+    const KEYS: &'static [&'static str] = &[
+        "host",
+        "port",
+        "url",
+        "token",
+        "plugin_name",
+        "plugin_developer",
+        "encrypt_token",
+        "instances",
+        "timeout",
+    ];
+
+    fn unknown_key(key: &str) -> anyhow::Error {
+        anyhow!(
+            "Unknown config key `{}`. Should be one of: {}",
+            key,
+            Self::KEYS.join(", ")
+        )
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<String>> {
+        Ok(match key.replace('-', "_").as_str() {
+            "host" => Some(self.host.clone()),
+            "port" => Some(self.port.to_string()),
+            "url" => self.url.clone(),
+            "token" => self.token.clone(),
+            "plugin_name" => Some(self.plugin_name.clone()),
+            "plugin_developer" => Some(self.plugin_developer.clone()),
+            "encrypt_token" => Some(self.encrypt_token.to_string()),
+            "instances" => Some(self.instances.join(",")).filter(|value| !value.is_empty()),
+            "timeout" => self.timeout.clone(),
+            _ => return Err(Self::unknown_key(key)),
+        })
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        match key.replace('-', "_").as_str() {
+            "host" => self.host = value.to_owned(),
+            "port" => {
+                self.port = value
+                    .parse()
+                    .with_context(|| format!("invalid port `{}`", value))?
+            }
+            "url" => {
+                socket_address(value)?;
+                self.url = Some(value.to_owned());
+            }
+            "token" => self.token = Some(value.to_owned()),
+            "plugin_name" => self.plugin_name = value.to_owned(),
+            "plugin_developer" => self.plugin_developer = value.to_owned(),
+            "encrypt_token" => {
+                self.encrypt_token = value
+                    .parse()
+                    .with_context(|| format!("invalid boolean `{}`", value))?
+            }
+            "instances" => {
+                self.instances = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .map(str::to_owned)
+                    .collect()
+            }
+            "timeout" => {
+                parse_duration::parse(value)
+                    .with_context(|| format!("invalid duration `{}`", value))?;
+                self.timeout = Some(value.to_owned());
+            }
+            _ => return Err(Self::unknown_key(key)),
+        }
+
+        Ok(())
+    }
+
+    pub fn unset(&mut self, key: &str) -> Result<()> {
+        let default = Config::default();
+
+        match key.replace('-', "_").as_str() {
+            "host" => self.host = default.host,
+            "port" => self.port = default.port,
+            "url" => self.url = None,
+            "token" => self.token = None,
+            "plugin_name" => self.plugin_name = default.plugin_name,
+            "plugin_developer" => self.plugin_developer = default.plugin_developer,
+            "encrypt_token" => self.encrypt_token = false,
+            "instances" => self.instances.clear(),
+            "timeout" => self.timeout = None,
+            _ => return Err(Self::unknown_key(key)),
+        }
+
+        Ok(())
+    }
+
+    /// WebSocket URL of the VTube Studio API, with any overrides from the command line applied.
+    /// Overriding the host or port ignores the `url` in the config file.
+    pub fn api_url(&self, url: Option<&str>, host: Option<&str>, port: Option<u16>) -> String {
+        if let Some(url) = url {
+            return url.to_owned();
+        }
+
+        match (&self.url, host, port) {
+            (Some(url), None, None) => url.clone(),
+            _ => format!(
+                "ws://{}:{}",
+                host.unwrap_or(&self.host),
+                port.unwrap_or(self.port)
+            ),
+        }
+    }
+
+    /// Parses the default request timeout, if set.
+    pub fn request_timeout(&self) -> Result<Option<Duration>> {
+        self.timeout
+            .as_deref()
+            .map(|timeout| {
+                parse_duration::parse(timeout)
+                    .with_context(|| format!("invalid timeout `{}` in config file", timeout))
+            })
+            .transpose()
+    }
+
+    /// Gets the token for the given plugin name and developer.
+    pub fn token_for(&self, plugin_name: &str, plugin_developer: &str) -> Option<&String> {
+        if plugin_name == self.plugin_name && plugin_developer == self.plugin_developer {
+            return self.token.as_ref();
+        }
+
+        self.other_tokens
+            .iter()
+            .find(|other| {
+                other.plugin_name == plugin_name && other.plugin_developer == plugin_developer
+            })
+            .map(|other| &other.token)
+    }
+
+    /// Sets the token for the given plugin name and developer.
+    pub fn set_token_for(&mut self, plugin_name: &str, plugin_developer: &str, token: String) {
+        if plugin_name == self.plugin_name && plugin_developer == self.plugin_developer {
+            self.token = Some(token);
+            return;
+        }
+
+        self.other_tokens.retain(|other| {
+            other.plugin_name != plugin_name || other.plugin_developer != plugin_developer
+        });
+        self.other_tokens.push(PluginToken {
+            plugin_name: plugin_name.to_owned(),
+            plugin_developer: plugin_developer.to_owned(),
+            token,
+        });
+    }
+
+    /// Returns the config as it should be stored, with tokens encrypted if enabled.
+    pub fn encrypted(&self) -> Result<Config> {
+        let mut conf = self.clone();
+
+        if self.encrypt_token {
+            let tokens = conf
+                .token
+                .iter_mut()
+                .chain(conf.other_tokens.iter_mut().map(|other| &mut other.token));
+
+            for token in tokens {
+                if !crypto::is_encrypted(token) {
+                    *token = crypto::encrypt(token)?;
+                }
+            }
+        }
+
+        Ok(conf)
+    }
+
+    /// Returns the config with tokens decrypted, if they were stored encrypted.
+    pub fn decrypted(&self) -> Result<Config> {
+        let mut conf = self.clone();
+
+        let tokens = conf
+            .token
+            .iter_mut()
+            .chain(conf.other_tokens.iter_mut().map(|other| &mut other.token));
+
+        for token in tokens {
+            if crypto::is_encrypted(token) {
+                *token = crypto::decrypt(token).context("failed to decrypt token")?;
+            }
+        }
+
+        Ok(conf)
+    }
+}
+
+/// Upgrades the layout of a config file from older versions to the current version, so that
+/// existing config files keep working when the layout changes. Changes are only saved the next
+/// time the config file is written.
+fn migrate(value: &mut Value) -> Result<()> {
+    let root = value
+        .as_object_mut()
+        .context("config file should contain an object")?;
+
+    let version = root.get("version").and_then(Value::as_u64).unwrap_or(0);
+    if version > CURRENT_VERSION {
+        bail!(
+            "config file version {} is newer than the supported version {} (try upgrading `vts`)",
+            version,
+            CURRENT_VERSION
+        );
+    }
+
+    if version < 1 {
+        migrate_profile_v1(root)?;
+
+        if let Some(Value::Object(profiles)) = root.get_mut("profiles") {
+            for profile in profiles.values_mut() {
+                if let Value::Object(profile) = profile {
+                    migrate_profile_v1(profile)?;
+                }
+            }
+        }
+    }
+
+    if version != CURRENT_VERSION {
+        debug!(from = version, to = CURRENT_VERSION, "Migrated config file");
+    }
+
+    root.insert("version".to_owned(), CURRENT_VERSION.into());
+    Ok(())
+}
+
+/// Version 0 configs had no `version` field, and may be missing fields or use alternative field
+/// names (e.g., from hand-edited files).
+fn migrate_profile_v1(profile: &mut Map<String, Value>) -> Result<()> {
+    const RENAMES: &[(&str, &str)] = &[
+        ("pluginName", "plugin_name"),
+        ("pluginDeveloper", "plugin_developer"),
+        ("developer_name", "plugin_developer"),
+        ("developerName", "plugin_developer"),
+        ("authToken", "token"),
+    ];
+
+    for (old, new) in RENAMES {
+        if let Some(value) = profile.remove(*old) {
+            profile.entry(*new).or_insert(value);
+        }
+    }
+
+    if let Value::Object(defaults) = serde_json::to_value(Config::default())? {
+        for (key, value) in defaults {
+            profile.entry(key).or_insert(value);
+        }
+    }
+
+    Ok(())
+}
+
+/// Result of one of the checks performed by `config validate`.
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub passed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl Check {
+    fn new(name: &'static str, result: Result<String>) -> Self {
+        match result {
+            Ok(message) => Self {
+                name,
+                passed: true,
+                message: Some(message),
+            },
+            Err(e) => Self {
+                name,
+                passed: false,
+                message: Some(format!("{:#}", e)),
+            },
+        }
+    }
+}
+
+/// Host and port to connect to for a WebSocket URL, with the port defaulting to 80 for `ws://`
+/// and 443 for `wss://`.
+pub fn socket_address(url: &str) -> Result<(String, u16)> {
+    let parsed = url::Url::parse(url).with_context(|| format!("invalid URL `{}`", url))?;
+    if !matches!(parsed.scheme(), "ws" | "wss") {
+        bail!("URL `{}` should start with `ws://` or `wss://`", url);
+    }
+
+    let host = parsed
+        .host_str()
+        .with_context(|| format!("URL `{}` has no host", url))?;
+    let port = parsed
+        .port_or_known_default()
+        .with_context(|| format!("URL `{}` has no port", url))?;
+
+    // IPv6 addresses are bracketed in URLs, but not when connecting
+    Ok((host.trim_matches(|c| c == '[' || c == ']').to_owned(), port))
+}
+
+/// Checks that the config file can be parsed, that VTube Studio is reachable at the configured
+/// host and port (or URL), and that the stored token is still valid. Checks stop at the first
+/// failure, since later checks depend on earlier ones.
+pub async fn validate(
+    config_path: &Path,
+    profile: Option<&str>,
+    url: Option<&str>,
+    host: Option<&str>,
+    port: Option<u16>,
+) -> Vec<Check> {
+    let mut checks = Vec::new();
+
+    let conf = ConfigFile::read(config_path)
+        .and_then(|config_file| config_file.profile(profile)?.decrypted());
+    let conf = match conf {
+        Ok(conf) => {
+            checks.push(Check::new(
+                "config",
+                Ok(format!("parsed {:?}", config_path)),
+            ));
+            conf
+        }
+        Err(e) => {
+            checks.push(Check::new("config", Err(e)));
+            return checks;
+        }
+    };
+
+    let url = conf.api_url(url, host, port);
+    let (host, port) = match socket_address(&url) {
+        Ok(address) => address,
+        Err(e) => {
+            checks.push(Check::new("url", Err(e)));
+            return checks;
+        }
+    };
+
+    let addr = match tokio::net::lookup_host((host.as_str(), port))
+        .await
+        .map(|mut addrs| addrs.next())
+    {
+        Ok(Some(addr)) => {
+            checks.push(Check::new(
+                "host",
+                Ok(format!("`{}` resolved to {}", host, addr.ip())),
+            ));
+            addr
+        }
+        Ok(None) => {
+            let e = anyhow!("`{}` did not resolve to any addresses", host);
+            checks.push(Check::new("host", Err(e)));
+            return checks;
+        }
+        Err(e) => {
+            let e = anyhow::Error::new(e).context(format!("failed to resolve `{}`", host));
+            checks.push(Check::new("host", Err(e)));
+            return checks;
+        }
+    };
+
+    let connect = tokio::time::timeout(CHECK_TIMEOUT, tokio::net::TcpStream::connect(addr)).await;
+    let connected = match connect {
+        Ok(Ok(_)) => Ok(format!("connected to {}", addr)),
+        Ok(Err(e)) => Err(anyhow::Error::new(e).context(format!("failed to connect to {}", addr))),
+        Err(_) => Err(anyhow!("timed out connecting to {}", addr)),
+    };
+    let passed = connected.is_ok();
+    checks.push(Check::new("port", connected));
+    if !passed {
+        return checks;
+    }
+
+    checks.push(Check::new("token", check_token(&conf, &url).await));
+    checks
+}
+
+/// Environment variable containing the token, used if one isn't given on the command line. This
+/// is read by `read_token` rather than the argument parser, so that `-` (for stdin) is handled the
+/// same way for `config init` as for other commands.
+const TOKEN_ENV: &str = "VTS_TOKEN";
+
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+async fn check_token(conf: &Config, url: &str) -> Result<String> {
+    let token = conf
+        .token
+        .clone()
+        .context("no token in config file (try running `vts config init`)")?;
+
+    // Authenticate manually, so that an invalid token doesn't trigger a new token request
+    let (mut client, _events) = Client::builder().url(url).build_tungstenite();
+
+    let req = AuthenticationRequest {
+        plugin_name: conf.plugin_name.clone().into(),
+        plugin_developer: conf.plugin_developer.clone().into(),
+        authentication_token: token,
+    };
+
+    let resp = tokio::time::timeout(CHECK_TIMEOUT, client.send(&req))
+        .await
+        .context("timed out waiting for authentication response")??;
+
+    if resp.authenticated {
+        Ok("token is valid".to_owned())
+    } else {
+        bail!("token was rejected: {}", resp.reason)
+    }
+}
+
+/// Reads a token given on the command line (or via environment variables), either directly or
+/// from a file, where `-` means stdin.
+pub fn read_token(token: Option<&str>, token_file: Option<&Path>) -> Result<Option<String>> {
+    let env_token = std::env::var(TOKEN_ENV)
+        .ok()
+        .filter(|token| !token.is_empty());
+    let token = token.or(env_token.as_deref());
+
+    let contents = match (token, token_file) {
+        (_, Some(path)) if path == Path::new("-") => read_stdin()?,
+        (_, Some(path)) => std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read token from {:?}", path))?,
+        (Some("-"), None) => read_stdin()?,
+        (Some(token), None) => token.to_owned(),
+        (None, None) => return Ok(None),
+    };
+
+    let token = contents.trim();
+    if token.is_empty() {
+        bail!("token is empty");
+    }
+
+    Ok(Some(token.to_owned()))
+}
+
+fn read_stdin() -> Result<String> {
+    let mut contents = String::new();
+    std::io::stdin()
+        .read_to_string(&mut contents)
+        .context("failed to read token from stdin")?;
+    Ok(contents)
+}
+
+/// Gets the default config file path. If a format isn't specified, this uses whichever config
+/// file already exists (preferring JSON).
+pub fn default_path(format: Option<ConfigFormat>) -> Result<PathBuf> {
+    let dir = directories::ProjectDirs::from("com.github", "walfie", "vtubestudio-cli")
+        .context("failed to get base directory")?
+        .config_dir()
+        .to_path_buf();
+
+    let path_for = |format: ConfigFormat| dir.join(format!("config.{}", format.extension()));
+
+    if let Some(format) = format {
+        return Ok(path_for(format));
+    }
+
+    Ok(ConfigFormat::ALL
+        .iter()
+        .map(|format| path_for(*format))
+        .find(|path| path.exists())
+        .unwrap_or_else(|| path_for(ConfigFormat::Json)))
+}
@@ -0,0 +1,124 @@
+use anyhow::{anyhow, bail, Context, Result};
+use argon2::Argon2;
+use base64::Engine;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+
+/// Prefix for encrypted tokens stored in the config file. The full format is
+/// `encrypted:<key source>:<base64 of salt, nonce, and ciphertext>`.
+const PREFIX: &str = "encrypted:";
+
+/// Environment variable containing the passphrase used to encrypt the token. If unset, a key
+/// derived from the machine ID is used instead.
+pub const PASSPHRASE_ENV: &str = "VTS_TOKEN_PASSPHRASE";
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+pub fn is_encrypted(token: &str) -> bool {
+    token.starts_with(PREFIX)
+}
+
+pub fn encrypt(token: &str) -> Result<String> {
+    let (source, secret) = match std::env::var(PASSPHRASE_ENV) {
+        Ok(passphrase) => ("passphrase", passphrase),
+        Err(_) => ("machine", machine_secret()?),
+    };
+
+    let mut salt = [0; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+
+    let cipher = cipher(&secret, &salt)?;
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, token.as_bytes())
+        .map_err(|_| anyhow!("failed to encrypt token"))?;
+
+    let mut data = Vec::with_capacity(SALT_LEN + NONCE_LEN + ciphertext.len());
+    data.extend_from_slice(&salt);
+    data.extend_from_slice(&nonce);
+    data.extend_from_slice(&ciphertext);
+
+    Ok(format!(
+        "{}{}:{}",
+        PREFIX,
+        source,
+        base64::engine::general_purpose::STANDARD.encode(data)
+    ))
+}
+
+pub fn decrypt(token: &str) -> Result<String> {
+    let (source, encoded) = token
+        .strip_prefix(PREFIX)
+        .and_then(|rest| rest.split_once(':'))
+        .context("invalid encrypted token format")?;
+
+    let secret = match source {
+        "passphrase" => std::env::var(PASSPHRASE_ENV).with_context(|| {
+            format!(
+                "the token is encrypted with a passphrase, but `{}` is not set",
+                PASSPHRASE_ENV
+            )
+        })?,
+        "machine" => machine_secret()?,
+        other => bail!("unknown token encryption key source `{}`", other),
+    };
+
+    let data = base64::engine::general_purpose::STANDARD
+        .decode(encoded)
+        .context("invalid encrypted token encoding")?;
+    if data.len() < SALT_LEN + NONCE_LEN {
+        bail!("encrypted token is too short");
+    }
+
+    let (salt, rest) = data.split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+
+    let plaintext = cipher(&secret, salt)?
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| match source {
+            "passphrase" => anyhow!("failed to decrypt token (is the passphrase correct?)"),
+            _ => anyhow!("failed to decrypt token (was it encrypted on another machine?)"),
+        })?;
+
+    String::from_utf8(plaintext).context("decrypted token is not valid UTF-8")
+}
+
+fn cipher(secret: &str, salt: &[u8]) -> Result<ChaCha20Poly1305> {
+    let mut key = [0; 32];
+    Argon2::default()
+        .hash_password_into(secret.as_bytes(), salt, &mut key)
+        .map_err(|e| anyhow!("failed to derive encryption key: {}", e))?;
+
+    Ok(ChaCha20Poly1305::new(Key::from_slice(&key)))
+}
+
+fn machine_secret() -> Result<String> {
+    machine_uid::get().map_err(|e| anyhow!("failed to get machine ID: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Both cases are in one test, since they depend on the same environment variable
+    #[test]
+    fn round_trip_with_passphrase() {
+        std::env::set_var(PASSPHRASE_ENV, "correct horse");
+        let encrypted = encrypt("abc123").unwrap();
+        assert!(is_encrypted(&encrypted));
+        assert!(encrypted.starts_with("encrypted:passphrase:"));
+        assert_eq!(decrypt(&encrypted).unwrap(), "abc123");
+
+        std::env::set_var(PASSPHRASE_ENV, "battery staple");
+        assert!(decrypt(&encrypted).is_err());
+    }
+
+    #[test]
+    fn invalid_format() {
+        assert!(!is_encrypted("abc123"));
+        assert!(decrypt("encrypted:passphrase").is_err());
+        assert!(decrypt("encrypted:unknown:AAAA").is_err());
+    }
+}
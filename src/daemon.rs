@@ -0,0 +1,223 @@
+use crate::args::Args;
+use crate::events::sleep_until;
+use crate::exec::check_supported;
+use crate::{next_keepalive, ping, run_command, CAPTURED};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cell::RefCell;
+use structopt::StructOpt;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tracing::{error, info, warn};
+use vtubestudio::Client;
+
+/// Message sent by a `--via-daemon` client: the full command line arguments (without the program
+/// name), parsed by the daemon the same way as a normal invocation.
+#[derive(Debug, Serialize, Deserialize)]
+struct DaemonRequest {
+    args: Vec<String>,
+}
+
+/// Reply from the daemon: the values the command printed, or an error message.
+#[derive(Debug, Serialize, Deserialize)]
+struct DaemonResponse {
+    #[serde(default)]
+    output: Vec<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Default socket path (Unix) or pipe name (Windows) for the daemon. On Unix, the socket lives in
+/// a per-user directory (`$XDG_RUNTIME_DIR` if set, otherwise the data directory) rather than the
+/// shared temp directory, so other users can't connect to it.
+pub fn default_socket() -> String {
+    if cfg!(windows) {
+        return r"\\.\pipe\vtubestudio-cli".to_owned();
+    }
+
+    let dirs = directories::ProjectDirs::from("com.github", "walfie", "vtubestudio-cli");
+    let dir = dirs
+        .as_ref()
+        .and_then(|dirs| dirs.runtime_dir().or_else(|| Some(dirs.data_dir())))
+        .map(|dir| dir.to_owned())
+        .unwrap_or_else(std::env::temp_dir);
+
+    dir.join("vtubestudio-cli.sock")
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Binds the daemon socket, readable and writable only by the current user. Fails if another
+/// daemon is already listening on it; otherwise, a socket file left behind by a previous daemon is
+/// replaced.
+#[cfg(unix)]
+async fn bind(socket: &str) -> Result<tokio::net::UnixListener> {
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+    use std::path::Path;
+
+    let path = Path::new(socket);
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)
+            .with_context(|| format!("failed to create directory {:?}", dir))?;
+    }
+
+    if path.exists() {
+        if tokio::net::UnixStream::connect(path).await.is_ok() {
+            bail!("a daemon is already listening on {:?}", socket);
+        }
+        std::fs::remove_file(path)
+            .with_context(|| format!("failed to remove stale socket {:?}", socket))?;
+    }
+
+    let listener = tokio::net::UnixListener::bind(path)
+        .with_context(|| format!("failed to listen on {:?}", socket))?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+        .with_context(|| format!("failed to set permissions on {:?}", socket))?;
+
+    Ok(listener)
+}
+
+/// Accepts connections on the socket, running each client's command with the existing
+/// connection to VTube Studio. Clients are handled one at a time.
+pub async fn serve(client: &mut Client, socket: &str) -> Result<()> {
+    #[cfg(unix)]
+    {
+        let listener = bind(socket).await?;
+        info!(%socket, "Daemon listening");
+
+        let mut next_ping = next_keepalive();
+        loop {
+            tokio::select! {
+                accepted = listener.accept() => {
+                    let (stream, _) = accepted?;
+                    handle_connection(client, stream).await;
+                }
+                _ = sleep_until(next_ping) => {
+                    keepalive(client).await;
+                    next_ping = next_keepalive();
+                }
+            }
+        }
+    }
+
+    #[cfg(windows)]
+    {
+        use tokio::net::windows::named_pipe::ServerOptions;
+
+        let mut server = ServerOptions::new()
+            .first_pipe_instance(true)
+            .create(socket)
+            .with_context(|| format!("failed to create pipe {:?}", socket))?;
+        info!(%socket, "Daemon listening");
+
+        let mut next_ping = next_keepalive();
+        loop {
+            tokio::select! {
+                connected = server.connect() => {
+                    connected?;
+                    let connected =
+                        std::mem::replace(&mut server, ServerOptions::new().create(socket)?);
+                    handle_connection(client, connected).await;
+                }
+                _ = sleep_until(next_ping) => {
+                    keepalive(client).await;
+                    next_ping = next_keepalive();
+                }
+            }
+        }
+    }
+}
+
+/// Checks the connection while idle, so that a lost connection is logged when it happens rather
+/// than when the next command fails. The client reconnects on the next request.
+async fn keepalive(client: &mut Client) {
+    if let Err(e) = ping(client).await {
+        warn!(error = %format!("{:#}", e), "Lost connection to VTube Studio");
+    }
+}
+
+async fn handle_connection<S>(client: &mut Client, stream: S)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut stream = BufReader::new(stream);
+    let mut line = String::new();
+
+    let response = match stream.read_line(&mut line).await {
+        Ok(_) => match run_request(client, &line).await {
+            Ok(output) => DaemonResponse {
+                output,
+                error: None,
+            },
+            Err(e) => DaemonResponse {
+                output: Vec::new(),
+                error: Some(format!("{:#}", e)),
+            },
+        },
+        Err(e) => {
+            error!(error = %e, "Failed to read from daemon client");
+            return;
+        }
+    };
+
+    let result = async {
+        let mut bytes = serde_json::to_vec(&response)?;
+        bytes.push(b'\n');
+        stream.get_mut().write_all(&bytes).await?;
+        stream.get_mut().shutdown().await?;
+        Ok::<_, anyhow::Error>(())
+    };
+
+    if let Err(e) = result.await {
+        error!(error = %e, "Failed to reply to daemon client");
+    }
+}
+
+async fn run_request(client: &mut Client, line: &str) -> Result<Vec<Value>> {
+    let request: DaemonRequest =
+        serde_json::from_str(line).context("invalid request from daemon client")?;
+
+    let args = Args::from_iter_safe(std::iter::once("vts".to_owned()).chain(request.args))?;
+    check_supported(&args.command)?;
+
+    CAPTURED
+        .scope(RefCell::new(Vec::new()), async {
+            run_command(client, args.command).await?;
+            Ok::<_, anyhow::Error>(CAPTURED.with(|captured| captured.take()))
+        })
+        .await
+}
+
+/// Sends the command line arguments to the daemon, returning the values the command printed.
+pub async fn request(socket: &str, args: Vec<String>) -> Result<Vec<Value>> {
+    #[cfg(unix)]
+    let stream = tokio::net::UnixStream::connect(socket).await;
+    #[cfg(windows)]
+    let stream = tokio::net::windows::named_pipe::ClientOptions::new().open(socket);
+
+    let stream = stream.with_context(|| {
+        format!(
+            "failed to connect to daemon at {:?} (is `vts daemon` running?)",
+            socket
+        )
+    })?;
+    let mut stream = BufReader::new(stream);
+
+    let mut bytes = serde_json::to_vec(&DaemonRequest { args })?;
+    bytes.push(b'\n');
+    stream.get_mut().write_all(&bytes).await?;
+
+    let mut line = String::new();
+    stream.read_line(&mut line).await?;
+    let response: DaemonResponse =
+        serde_json::from_str(&line).context("invalid response from daemon")?;
+
+    match response.error {
+        Some(error) => bail!(error),
+        None => Ok(response.output),
+    }
+}
@@ -0,0 +1,224 @@
+use anyhow::{bail, Context, Error, Result};
+use futures_util::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::net::Ipv4Addr;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::time::Instant;
+use tracing::debug;
+use vtubestudio::data::ApiStateRequest;
+use vtubestudio::Client;
+
+/// UDP port that VTube Studio sends API state broadcasts to.
+pub const BROADCAST_PORT: u16 = 47779;
+
+/// How long to wait for a TCP connection when scanning, before assuming the port is closed.
+const SCAN_CONNECT_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// How long to wait for an API state response from an open port when scanning.
+const SCAN_API_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Number of addresses probed at once when scanning.
+const SCAN_CONCURRENCY: usize = 256;
+
+/// A VTube Studio instance found via its UDP broadcast (or by scanning, in which case the
+/// instance ID and window title are unknown).
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Instance {
+    pub host: String,
+    pub port: u16,
+    pub active: bool,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub instance_id: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub window_title: String,
+}
+
+/// An IPv4 subnet to scan, e.g. `192.168.1.0/24` (or a single address).
+#[derive(Debug, Clone)]
+pub struct Subnet {
+    network: u32,
+    prefix: u32,
+}
+
+impl Subnet {
+    /// Addresses in the subnet, excluding the network and broadcast addresses (for subnets
+    /// larger than `/31`).
+    fn hosts(&self) -> impl Iterator<Item = Ipv4Addr> {
+        let size = 1u64 << (32 - self.prefix);
+        let (first, last) = if size > 2 {
+            (1, size - 2)
+        } else {
+            (0, size - 1)
+        };
+        let network = self.network as u64;
+
+        (first..=last).map(move |offset| Ipv4Addr::from((network + offset) as u32))
+    }
+}
+
+impl FromStr for Subnet {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let (addr, prefix) = value.split_once('/').unwrap_or((value, "32"));
+        let addr: Ipv4Addr = addr
+            .parse()
+            .with_context(|| format!("invalid IPv4 address in `{}`", value))?;
+        let prefix: u32 = prefix
+            .parse()
+            .ok()
+            .filter(|prefix| *prefix <= 32)
+            .with_context(|| format!("invalid prefix length in `{}`", value))?;
+
+        if prefix < 16 {
+            bail!("subnet `{}` is too large to scan (the limit is /16)", value);
+        }
+
+        let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+        Ok(Subnet {
+            network: u32::from(addr) & mask,
+            prefix,
+        })
+    }
+}
+
+/// Ports to scan, e.g. `8001-8010` or `8001,8002`.
+#[derive(Debug, Clone)]
+pub struct Ports(Vec<u16>);
+
+impl FromStr for Ports {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let mut ports = Vec::new();
+
+        for part in value.split(',') {
+            let parse = |port: &str| {
+                port.trim()
+                    .parse::<u16>()
+                    .with_context(|| format!("invalid port `{}`", port))
+            };
+
+            match part.split_once('-') {
+                Some((start, end)) => ports.extend(parse(start)?..=parse(end)?),
+                None => ports.push(parse(part)?),
+            }
+        }
+
+        if ports.is_empty() {
+            bail!("no ports in `{}`", value);
+        }
+
+        Ok(Ports(ports))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Broadcast {
+    #[serde(rename = "messageType")]
+    message_type: String,
+    data: BroadcastData,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BroadcastData {
+    active: bool,
+    port: u16,
+    #[serde(rename = "instanceID")]
+    instance_id: String,
+    #[serde(default)]
+    window_title: String,
+}
+
+/// Listens for API state broadcasts until the timeout elapses, returning each distinct instance
+/// (by instance ID) in the order they were first seen.
+pub async fn discover(timeout: Duration) -> Result<Vec<Instance>> {
+    let socket = UdpSocket::bind(("0.0.0.0", BROADCAST_PORT))
+        .await
+        .with_context(|| format!("failed to listen on UDP port {}", BROADCAST_PORT))?;
+
+    let deadline = Instant::now() + timeout;
+    let mut instances: Vec<Instance> = Vec::new();
+    let mut buf = vec![0; 65536];
+
+    while let Ok(received) = tokio::time::timeout_at(deadline, socket.recv_from(&mut buf)).await {
+        let (len, addr) = received.context("failed to receive UDP broadcast")?;
+
+        let broadcast = match serde_json::from_slice::<Broadcast>(&buf[..len]) {
+            Ok(broadcast) if broadcast.message_type == "VTubeStudioAPIStateBroadcast" => broadcast,
+            _ => {
+                debug!(%addr, "Ignoring unrecognized UDP message");
+                continue;
+            }
+        };
+
+        let instance = Instance {
+            host: addr.ip().to_string(),
+            port: broadcast.data.port,
+            active: broadcast.data.active,
+            instance_id: broadcast.data.instance_id,
+            window_title: broadcast.data.window_title,
+        };
+
+        match instances
+            .iter_mut()
+            .find(|existing| existing.instance_id == instance.instance_id)
+        {
+            Some(existing) => *existing = instance,
+            None => instances.push(instance),
+        }
+    }
+
+    Ok(instances)
+}
+
+/// Probes every address and port in the subnet for the VTube Studio API, by connecting and
+/// sending an API state request. Useful on networks where UDP broadcasts are blocked.
+pub async fn scan(subnet: &Subnet, ports: &Ports) -> Result<Vec<Instance>> {
+    let targets: Vec<(Ipv4Addr, u16)> = subnet
+        .hosts()
+        .flat_map(|addr| ports.0.iter().map(move |port| (addr, *port)))
+        .collect();
+
+    let mut found: Vec<(Ipv4Addr, u16, bool)> = stream::iter(targets)
+        .map(|(addr, port)| probe(addr, port))
+        .buffer_unordered(SCAN_CONCURRENCY)
+        .filter_map(|found| async move { found })
+        .collect()
+        .await;
+    found.sort();
+
+    Ok(found
+        .into_iter()
+        .map(|(addr, port, active)| Instance {
+            host: addr.to_string(),
+            port,
+            active,
+            instance_id: String::new(),
+            window_title: String::new(),
+        })
+        .collect())
+}
+
+/// Returns the address, port, and whether the API is active, if VTube Studio is listening there.
+async fn probe(addr: Ipv4Addr, port: u16) -> Option<(Ipv4Addr, u16, bool)> {
+    tokio::time::timeout(SCAN_CONNECT_TIMEOUT, TcpStream::connect((addr, port)))
+        .await
+        .ok()?
+        .ok()?;
+    debug!(%addr, port, "Found open port, checking for VTube Studio API");
+
+    let (mut client, _events) = Client::builder()
+        .url(format!("ws://{}:{}", addr, port))
+        .build_tungstenite();
+    let state = tokio::time::timeout(SCAN_API_TIMEOUT, client.send(&ApiStateRequest {}))
+        .await
+        .ok()?
+        .ok()?;
+
+    Some((addr, port, state.active))
+}
@@ -0,0 +1,1017 @@
+use crate::args::{EventFilter, EventOptions, LogRotation, TimestampFormat};
+use crate::{check_dry_run, print_value};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local, NaiveDate, SecondsFormat, Utc};
+use futures_util::stream::SplitSink;
+use futures_util::{SinkExt, StreamExt};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use rosc::{OscMessage, OscPacket, OscType};
+use rumqttc::{AsyncClient, MqttOptions, QoS};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::process::Command;
+use tokio::sync::mpsc;
+use tokio::task::{JoinHandle, JoinSet};
+use tokio::time::Instant;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use tracing::{error, info, warn};
+use url::Url;
+use vtubestudio::data::{Event, EventSubscriptionRequest};
+use vtubestudio::Client;
+
+/// Prints received events, and forwards them to any configured event hooks.
+pub struct EventHandler {
+    filters: Vec<EventFilter>,
+    throttle: Option<Duration>,
+    debounce: Option<Duration>,
+    /// When the last event of each type was handled, for throttling.
+    last_handled: HashMap<String, Instant>,
+    /// Most recent event of each type, when it should be handled, and when it was received, for
+    /// debouncing.
+    pending: HashMap<String, (Instant, DateTime<Utc>, Value)>,
+    timestamps: Option<TimestampFormat>,
+    /// Next sequence number, if sequence numbers are enabled.
+    sequence: Option<u64>,
+    reconnect: bool,
+    summary: Option<Summary>,
+    exec: Option<String>,
+    webhook: Option<Webhook>,
+    /// Webhook requests still in progress, so that they can finish before exiting.
+    webhook_posts: JoinSet<()>,
+    mqtt: Option<Mqtt>,
+    osc: Option<Osc>,
+    forward: Option<Forwarder>,
+    log_file: Option<LogFile>,
+    pipe: Option<Pipe>,
+    record: Option<File>,
+}
+
+impl EventHandler {
+    pub async fn new(options: &EventOptions) -> Result<Self> {
+        let webhook = match &options.webhook {
+            Some(url) => Some(Webhook::new(options, url)?),
+            None => None,
+        };
+
+        let mqtt = match &options.mqtt {
+            Some(broker) => Some(Mqtt::connect(broker, &options.mqtt_topic)?),
+            None => None,
+        };
+
+        let osc = match &options.osc {
+            Some(addr) => Some(Osc::connect(addr, &options.osc_prefix).await?),
+            None => None,
+        };
+
+        let forward = match &options.forward {
+            Some(url) => Some(Forwarder::connect(url).await?),
+            None => None,
+        };
+
+        let log_file = options
+            .log_file
+            .as_ref()
+            .map(|path| LogFile::new(path, options.rotate, options.rotate_size));
+
+        let pipe = match &options.pipe {
+            Some(path) => Some(Pipe::open(path)?),
+            None => None,
+        };
+
+        let record = match &options.record {
+            Some(path) => Some(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .with_context(|| format!("failed to open recording file {:?}", path))?,
+            ),
+            None => None,
+        };
+
+        Ok(Self {
+            filters: options.filters.clone(),
+            throttle: options.throttle,
+            debounce: options.debounce,
+            last_handled: HashMap::new(),
+            pending: HashMap::new(),
+            timestamps: options.timestamps,
+            sequence: options.sequence.then_some(0),
+            reconnect: !options.no_reconnect,
+            summary: options.summary.map(Summary::new),
+            exec: options.exec.clone(),
+            webhook,
+            webhook_posts: JoinSet::new(),
+            mqtt,
+            osc,
+            forward,
+            log_file,
+            pipe,
+            record,
+        })
+    }
+
+    pub async fn handle(&mut self, event: &Event) -> Result<()> {
+        self.handle_value(serde_json::to_value(event)?).await
+    }
+
+    pub async fn handle_value(&mut self, value: Value) -> Result<()> {
+        // Captured here rather than when printing, since debounced events are printed later
+        let received_at = Utc::now();
+
+        if let Some(record) = &mut self.record {
+            let mut line = serde_json::to_vec(&serde_json::json!({
+                "receivedAt": received_at.timestamp_millis(),
+                "event": &value,
+            }))?;
+            line.push(b'\n');
+            record.write_all(&line)?;
+        }
+
+        if !self.filters.iter().all(|filter| filter.matches(&value)) {
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        let name = event_type(&value).to_owned();
+
+        if let Some(debounce) = self.debounce {
+            self.pending
+                .insert(name, (now + debounce, received_at, value));
+            return Ok(());
+        }
+
+        if let Some(throttle) = self.throttle {
+            match self.last_handled.get(&name) {
+                Some(last) if now.duration_since(*last) < throttle => return Ok(()),
+                _ => {
+                    self.last_handled.insert(name, now);
+                }
+            }
+        }
+
+        self.emit(value, received_at).await
+    }
+
+    /// Whether to reconnect and resubscribe to events after being disconnected.
+    pub fn should_reconnect(&self) -> bool {
+        self.reconnect
+    }
+
+    /// The next time at which a debounced event is due to be handled, or a summary printed.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        let debounce_deadline = self
+            .pending
+            .values()
+            .map(|(deadline, _, _)| *deadline)
+            .min();
+        let summary_deadline = self.summary.as_ref().map(Summary::next_deadline);
+
+        debounce_deadline.into_iter().chain(summary_deadline).min()
+    }
+
+    /// Handles debounced events whose deadline has passed, and prints the summary if it's due.
+    pub async fn flush_due(&mut self) {
+        let now = Instant::now();
+
+        if let Some(summary) = &mut self.summary {
+            if summary.next_deadline() <= now {
+                if let Err(e) = print_value(&summary.take(now)) {
+                    error!(error = %e, "Failed to print event summary");
+                }
+            }
+        }
+
+        let due = self
+            .pending
+            .iter()
+            .filter(|(_, (deadline, _, _))| *deadline <= now)
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+
+        for name in due {
+            if let Some((_, received_at, value)) = self.pending.remove(&name) {
+                if let Err(e) = self.emit(value, received_at).await {
+                    error!(error = %e, "Failed to handle event");
+                }
+            }
+        }
+    }
+
+    /// Handles all pending debounced events, regardless of deadline, and waits (for a limited
+    /// time) for any webhook requests still in progress.
+    pub async fn flush_all(&mut self) {
+        const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+        for (_, (_, received_at, value)) in std::mem::take(&mut self.pending) {
+            if let Err(e) = self.emit(value, received_at).await {
+                error!(error = %e, "Failed to handle event");
+            }
+        }
+
+        if self.webhook_posts.is_empty() {
+            return;
+        }
+
+        let posts = &mut self.webhook_posts;
+        let drain = async { while posts.join_next().await.is_some() {} };
+        if tokio::time::timeout(WEBHOOK_TIMEOUT, drain).await.is_err() {
+            warn!(
+                pending = self.webhook_posts.len(),
+                timeout = ?WEBHOOK_TIMEOUT,
+                "Webhook requests didn't finish in time, cancelling them"
+            );
+            self.webhook_posts.abort_all();
+        }
+    }
+
+    /// Wraps the event with metadata for printing/logging, if enabled.
+    fn wrap(&mut self, event: &Value, received_at: DateTime<Utc>) -> Value {
+        if self.timestamps.is_none() && self.sequence.is_none() {
+            return event.clone();
+        }
+
+        let mut record = Map::new();
+
+        if let Some(seq) = &mut self.sequence {
+            record.insert("seq".to_owned(), (*seq).into());
+            *seq += 1;
+        }
+
+        if let Some(format) = self.timestamps {
+            let received_at = match format {
+                TimestampFormat::Rfc3339 => received_at
+                    .to_rfc3339_opts(SecondsFormat::Millis, true)
+                    .into(),
+                TimestampFormat::UnixMillis => received_at.timestamp_millis().into(),
+            };
+            record.insert("receivedAt".to_owned(), received_at);
+        }
+
+        record.insert("event".to_owned(), event.clone());
+        Value::Object(record)
+    }
+
+    async fn emit(&mut self, value: Value, received_at: DateTime<Utc>) -> Result<()> {
+        let record = self.wrap(&value, received_at);
+        match &mut self.summary {
+            Some(summary) => summary.record(event_type(&value)),
+            None => print_value(&record)?,
+        }
+
+        // A failing output shouldn't stop the event from reaching the others
+        if let Some(log_file) = &mut self.log_file {
+            if let Err(e) = log_file.append(&record) {
+                error!(error = %format!("{:#}", e), "Failed to write event to log file");
+            }
+        }
+
+        if let Some(pipe) = &self.pipe {
+            if let Err(e) = pipe.write(&record) {
+                error!(error = %format!("{:#}", e), "Failed to write event to pipe");
+            }
+        }
+
+        if let Some(command) = &self.exec {
+            if let Err(e) = spawn_hook(command, &value) {
+                error!(error = %format!("{:#}", e), "Failed to run event hook");
+            }
+        }
+
+        if let Some(mqtt) = &self.mqtt {
+            if let Err(e) = mqtt.publish(&value) {
+                error!(error = %format!("{:#}", e), "Failed to publish event to MQTT");
+            }
+        }
+
+        if let Some(osc) = &self.osc {
+            if let Err(e) = osc.send(&value).await {
+                error!(error = %format!("{:#}", e), "Failed to send event over OSC");
+            }
+        }
+
+        if let Some(forward) = &mut self.forward {
+            if let Err(e) = forward.send(&value).await {
+                error!(error = %format!("{:#}", e), "Failed to forward event");
+            }
+        }
+
+        if let Some(webhook) = &self.webhook {
+            // Finished requests are removed as we go, so that they don't accumulate
+            while self.webhook_posts.try_join_next().is_some() {}
+            webhook.spawn_post(&mut self.webhook_posts, value);
+        }
+
+        Ok(())
+    }
+}
+
+/// Event counts per event type, for `--summary`.
+struct Summary {
+    interval: Duration,
+    window_start: Instant,
+    counts: BTreeMap<String, u64>,
+    totals: BTreeMap<String, u64>,
+}
+
+impl Summary {
+    fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            window_start: Instant::now(),
+            counts: BTreeMap::new(),
+            totals: BTreeMap::new(),
+        }
+    }
+
+    fn next_deadline(&self) -> Instant {
+        self.window_start + self.interval
+    }
+
+    fn record(&mut self, event_type: &str) {
+        *self.counts.entry(event_type.to_owned()).or_default() += 1;
+        *self.totals.entry(event_type.to_owned()).or_default() += 1;
+    }
+
+    /// Returns the summary for the current window, and starts a new window.
+    fn take(&mut self, now: Instant) -> Value {
+        let elapsed = now.duration_since(self.window_start).as_secs_f64();
+        let counts = std::mem::take(&mut self.counts);
+
+        let events = self
+            .totals
+            .iter()
+            .map(|(name, total)| {
+                let count = counts.get(name).copied().unwrap_or_default();
+                let summary = serde_json::json!({
+                    "count": count,
+                    "ratePerSecond": if elapsed > 0.0 { count as f64 / elapsed } else { 0.0 },
+                    "total": total,
+                });
+
+                (name.clone(), summary)
+            })
+            .collect::<Map<_, _>>();
+
+        self.window_start = now;
+
+        serde_json::json!({
+            "summary": {
+                "intervalSeconds": elapsed,
+                "events": events,
+            }
+        })
+    }
+}
+
+#[derive(Clone)]
+struct Webhook {
+    client: reqwest::Client,
+    url: String,
+    headers: HeaderMap,
+    retries: u32,
+}
+
+impl Webhook {
+    fn new(options: &EventOptions, url: &str) -> Result<Self> {
+        let mut headers = HeaderMap::new();
+        for header in &options.webhook_headers {
+            headers.insert(
+                HeaderName::from_bytes(header.name.as_bytes())
+                    .with_context(|| format!("invalid header name `{}`", header.name))?,
+                HeaderValue::from_str(&header.value)
+                    .with_context(|| format!("invalid value for header `{}`", header.name))?,
+            );
+        }
+
+        Ok(Self {
+            client: reqwest::Client::new(),
+            url: url.to_owned(),
+            headers,
+            retries: options.webhook_retries,
+        })
+    }
+
+    /// Posts the event in the background, retrying with exponential backoff on failure.
+    fn spawn_post(&self, tasks: &mut JoinSet<()>, event: Value) {
+        let webhook = self.clone();
+
+        tasks.spawn(async move {
+            let mut backoff = Duration::from_millis(500);
+
+            for attempt in 0..=webhook.retries {
+                match webhook.post(&event).await {
+                    Ok(()) => return,
+                    Err(e) if attempt < webhook.retries => {
+                        warn!(error = %e, ?backoff, "Webhook request failed, retrying");
+                        tokio::time::sleep(backoff).await;
+                        backoff *= 2;
+                    }
+                    Err(e) => error!(error = %e, url = %webhook.url, "Webhook request failed"),
+                }
+            }
+        });
+    }
+
+    async fn post(&self, event: &Value) -> Result<()> {
+        self.client
+            .post(&self.url)
+            .headers(self.headers.clone())
+            .json(event)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
+
+struct Mqtt {
+    client: AsyncClient,
+    topic_prefix: String,
+}
+
+impl Mqtt {
+    fn connect(broker: &str, topic_prefix: &str) -> Result<Self> {
+        let url = Url::parse(broker).with_context(|| format!("invalid MQTT URL `{}`", broker))?;
+        if !matches!(url.scheme(), "mqtt" | "tcp") {
+            bail!("unsupported MQTT URL scheme `{}`", url.scheme());
+        }
+
+        let host = url.host_str().context("MQTT URL is missing a host")?;
+        let client_id = format!("vtubestudio-cli-{}", std::process::id());
+        let mut options = MqttOptions::new(client_id, host, url.port().unwrap_or(1883));
+        options.set_keep_alive(Duration::from_secs(30));
+        if !url.username().is_empty() {
+            options.set_credentials(url.username(), url.password().unwrap_or_default());
+        }
+
+        let (client, mut event_loop) = AsyncClient::new(options, 64);
+
+        // The event loop needs to be polled for messages to actually be sent. It reconnects
+        // automatically on the next poll after an error.
+        tokio::spawn(async move {
+            loop {
+                if let Err(e) = event_loop.poll().await {
+                    warn!(error = %e, "MQTT connection error");
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            }
+        });
+
+        Ok(Self {
+            client,
+            topic_prefix: topic_prefix.trim_end_matches('/').to_owned(),
+        })
+    }
+
+    /// Queues the event to be published. If the queue is full (e.g., the broker is unreachable),
+    /// the event is dropped rather than blocking the handling of other events.
+    fn publish(&self, event: &Value) -> Result<()> {
+        let topic = format!("{}/{}", self.topic_prefix, event_type(event));
+        let payload = serde_json::to_vec(event)?;
+        if let Err(e) = self
+            .client
+            .try_publish(&topic, QoS::AtLeastOnce, false, payload)
+        {
+            warn!(error = %e, %topic, "MQTT queue is full, dropping event");
+        }
+
+        Ok(())
+    }
+}
+
+struct Osc {
+    socket: UdpSocket,
+    prefix: String,
+}
+
+impl Osc {
+    async fn connect(addr: &str, prefix: &str) -> Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0")
+            .await
+            .context("failed to bind UDP socket for OSC")?;
+        socket
+            .connect(addr)
+            .await
+            .with_context(|| format!("failed to resolve OSC address `{}`", addr))?;
+
+        Ok(Self {
+            socket,
+            prefix: prefix.trim_end_matches('/').to_owned(),
+        })
+    }
+
+    async fn send(&self, event: &Value) -> Result<()> {
+        let name = event_type(event);
+        let mut args = Vec::new();
+        if let Some(data) = event.get("data") {
+            push_osc_args(data, &mut args);
+        }
+
+        let packet = OscPacket::Message(OscMessage {
+            addr: format!("{}/{}", self.prefix, name.trim_end_matches("Event")),
+            args,
+        });
+        let bytes = rosc::encoder::encode(&packet).context("failed to encode OSC message")?;
+        self.socket.send(&bytes).await?;
+
+        Ok(())
+    }
+}
+
+/// Flattens scalar values (depth-first, in field order) into OSC arguments.
+fn push_osc_args(value: &Value, args: &mut Vec<OscType>) {
+    match value {
+        Value::Bool(b) => args.push(OscType::Bool(*b)),
+        Value::String(s) => args.push(OscType::String(s.clone())),
+        Value::Number(n) => match n.as_i64().and_then(|n| i32::try_from(n).ok()) {
+            Some(int) => args.push(OscType::Int(int)),
+            None => args.push(OscType::Float(n.as_f64().unwrap_or_default() as f32)),
+        },
+        Value::Array(values) => values.iter().for_each(|value| push_osc_args(value, args)),
+        Value::Object(fields) => fields.values().for_each(|value| push_osc_args(value, args)),
+        Value::Null => {}
+    }
+}
+
+/// Relays events to a downstream WebSocket server.
+type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+struct Forwarder {
+    url: String,
+    sink: Option<SplitSink<WsStream, Message>>,
+    /// Reads (and discards) incoming messages, so that pings get answered and unread messages
+    /// don't pile up.
+    reader: Option<JoinHandle<()>>,
+}
+
+impl Forwarder {
+    const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+    async fn connect(url: &str) -> Result<Self> {
+        let mut forwarder = Self {
+            url: url.to_owned(),
+            sink: None,
+            reader: None,
+        };
+
+        forwarder.reconnect().await?;
+        Ok(forwarder)
+    }
+
+    async fn reconnect(&mut self) -> Result<()> {
+        self.close();
+
+        let connect = tokio_tungstenite::connect_async(&self.url);
+        let (stream, _) = tokio::time::timeout(Self::CONNECT_TIMEOUT, connect)
+            .await
+            .with_context(|| {
+                format!(
+                    "timed out connecting to {} after {:?}",
+                    self.url,
+                    Self::CONNECT_TIMEOUT
+                )
+            })?
+            .with_context(|| format!("failed to connect to {}", self.url))?;
+
+        let (sink, mut incoming) = stream.split();
+        let url = self.url.clone();
+        self.reader = Some(tokio::spawn(async move {
+            while let Some(message) = incoming.next().await {
+                if let Err(e) = message {
+                    warn!(error = %e, %url, "Forwarding connection closed");
+                    break;
+                }
+            }
+        }));
+        self.sink = Some(sink);
+        Ok(())
+    }
+
+    fn close(&mut self) {
+        self.sink = None;
+        if let Some(reader) = self.reader.take() {
+            reader.abort();
+        }
+    }
+
+    /// Sends the event, reconnecting once if the downstream server went away.
+    async fn send(&mut self, event: &Value) -> Result<()> {
+        let text = serde_json::to_string(event)?;
+
+        if let Some(sink) = &mut self.sink {
+            match sink.send(Message::Text(text.clone())).await {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    warn!(error = %e, url = %self.url, "Failed to forward event, reconnecting")
+                }
+            }
+        }
+
+        self.reconnect().await?;
+
+        if let Some(sink) = &mut self.sink {
+            sink.send(Message::Text(text)).await?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Forwarder {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+/// Named pipe (FIFO on Unix), written to from a background task.
+struct Pipe {
+    sender: mpsc::Sender<Vec<u8>>,
+}
+
+impl Pipe {
+    /// Maximum number of events to buffer while no reader is connected.
+    const BUFFER_SIZE: usize = 1024;
+
+    fn open(path: &Path) -> Result<Self> {
+        let (sender, receiver) = mpsc::channel(Self::BUFFER_SIZE);
+        spawn_pipe_writer(path.to_owned(), receiver)?;
+        Ok(Self { sender })
+    }
+
+    fn write(&self, event: &Value) -> Result<()> {
+        let mut line = serde_json::to_vec(event)?;
+        line.push(b'\n');
+
+        if self.sender.try_send(line).is_err() {
+            warn!("Pipe buffer is full (is the reader connected?), dropping event");
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn spawn_pipe_writer(path: PathBuf, mut receiver: mpsc::Receiver<Vec<u8>>) -> Result<()> {
+    use nix::sys::stat::Mode;
+    use std::os::unix::fs::FileTypeExt;
+
+    match std::fs::metadata(&path) {
+        // Writing to a regular file would never block waiting for a reader, and would grow forever
+        Ok(metadata) if !metadata.file_type().is_fifo() => {
+            bail!("{:?} already exists and is not a FIFO", path);
+        }
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            nix::unistd::mkfifo(&path, Mode::from_bits_truncate(0o644))
+                .with_context(|| format!("failed to create FIFO at {:?}", path))?;
+        }
+        Err(e) => {
+            return Err(e).with_context(|| format!("failed to check pipe {:?}", path));
+        }
+    }
+
+    // Opening a FIFO for writing blocks until there is a reader, so use a dedicated thread
+    std::thread::spawn(move || loop {
+        let mut file = match OpenOptions::new().write(true).open(&path) {
+            Ok(file) => file,
+            Err(e) => {
+                error!(error = %e, ?path, "Failed to open pipe");
+                return;
+            }
+        };
+
+        loop {
+            match receiver.blocking_recv() {
+                Some(line) => {
+                    if let Err(e) = file.write_all(&line) {
+                        warn!(error = %e, ?path, "Pipe reader disconnected, waiting for new reader");
+                        break;
+                    }
+                }
+                None => return,
+            }
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(windows)]
+fn spawn_pipe_writer(path: PathBuf, mut receiver: mpsc::Receiver<Vec<u8>>) -> Result<()> {
+    use tokio::net::windows::named_pipe::ServerOptions;
+
+    let mut server = ServerOptions::new()
+        .first_pipe_instance(true)
+        .create(&path)
+        .with_context(|| format!("failed to create named pipe {:?}", path))?;
+
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = server.connect().await {
+                error!(error = %e, ?path, "Failed to wait for pipe reader");
+                return;
+            }
+
+            loop {
+                match receiver.recv().await {
+                    Some(line) => {
+                        if let Err(e) = server.write_all(&line).await {
+                            warn!(error = %e, ?path, "Pipe reader disconnected, waiting for new reader");
+                            break;
+                        }
+                    }
+                    None => return,
+                }
+            }
+
+            if let Err(e) = server.disconnect() {
+                error!(error = %e, ?path, "Failed to reset pipe");
+                return;
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// NDJSON log file, with optional rotation.
+struct LogFile {
+    path: PathBuf,
+    rotation: Option<LogRotation>,
+    max_size: u64,
+    current: Option<(File, PathBuf, NaiveDate)>,
+}
+
+impl LogFile {
+    fn new(path: &Path, rotation: Option<LogRotation>, max_size: u64) -> Self {
+        Self {
+            path: path.to_owned(),
+            rotation,
+            max_size,
+            current: None,
+        }
+    }
+
+    fn append(&mut self, event: &Value) -> Result<()> {
+        let mut line = serde_json::to_vec(event)?;
+        line.push(b'\n');
+
+        let today = Local::now().date_naive();
+        let is_stale = match &self.current {
+            Some((_, _, date)) => self.rotation == Some(LogRotation::Daily) && *date != today,
+            None => true,
+        };
+        if is_stale {
+            self.current = None;
+        }
+
+        if self.rotation == Some(LogRotation::Size) {
+            let is_full = match &self.current {
+                Some((file, _, _)) => {
+                    let size = file.metadata()?.len();
+                    size > 0 && size + line.len() as u64 > self.max_size
+                }
+                None => false,
+            };
+
+            if is_full {
+                // Close the file before renaming it
+                if let Some((_, path, _)) = self.current.take() {
+                    let rotated = rotated_path(&path);
+                    std::fs::rename(&path, &rotated)
+                        .with_context(|| format!("failed to rotate log file {:?}", path))?;
+                }
+            }
+        }
+
+        if self.current.is_none() {
+            let path = match self.rotation {
+                Some(LogRotation::Daily) => suffixed_path(&self.path, &today.format("%Y-%m-%d")),
+                _ => self.path.clone(),
+            };
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .with_context(|| format!("failed to open log file {:?}", path))?;
+
+            self.current = Some((file, path, today));
+        }
+
+        if let Some((file, _, _)) = &mut self.current {
+            file.write_all(&line)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Path to move a full log file to, e.g., `events-20240101-120000.ndjson`. A counter is added if
+/// that path already exists (e.g., when rotating more than once per second).
+fn rotated_path(path: &Path) -> PathBuf {
+    let timestamp = Local::now().format("%Y%m%d-%H%M%S").to_string();
+    let mut rotated = suffixed_path(path, &timestamp);
+    let mut count = 1;
+    while rotated.exists() {
+        rotated = suffixed_path(path, &format!("{}-{}", timestamp, count));
+        count += 1;
+    }
+
+    rotated
+}
+
+/// Appends a suffix to the file stem, e.g., `events.ndjson` to `events-suffix.ndjson`.
+fn suffixed_path(path: &Path, suffix: &impl std::fmt::Display) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let file_name = match path.extension() {
+        Some(ext) => format!("{}-{}.{}", stem, suffix, ext.to_string_lossy()),
+        None => format!("{}-{}", stem, suffix),
+    };
+
+    path.with_file_name(file_name)
+}
+
+/// Sends event subscription requests, logging the responses to stderr (since stdout is reserved
+/// for the events themselves). In `--dry-run` mode, the first request is printed instead.
+pub async fn subscribe(client: &mut Client, reqs: &[EventSubscriptionRequest]) -> Result<()> {
+    for req in reqs {
+        check_dry_run(req)?;
+        let resp = client.send(req).await?;
+        let resp_json = serde_json::to_string(&resp)?;
+        eprintln!("{resp_json}");
+    }
+
+    Ok(())
+}
+
+/// Resends event subscription requests until they succeed, with exponential backoff. The client
+/// reconnects (and reauthenticates) automatically when sending the first request.
+pub async fn resubscribe(client: &mut Client, reqs: &[EventSubscriptionRequest]) {
+    const MAX_BACKOFF: Duration = Duration::from_secs(30);
+    let mut backoff = Duration::from_secs(1);
+
+    warn!("Disconnected from VTube Studio, reconnecting...");
+
+    loop {
+        tokio::time::sleep(backoff).await;
+
+        match subscribe(client, reqs).await {
+            Ok(()) => {
+                info!("Reconnected and resubscribed to events");
+                return;
+            }
+            Err(e) => {
+                warn!(error = %e, ?backoff, "Failed to reconnect, retrying");
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+    }
+}
+
+/// Replays recorded events from an NDJSON file, preserving the time between events. Pending
+/// webhook requests are waited for before returning, even if replaying fails partway through.
+pub async fn replay(path: &Path, speed: f64, handler: &mut EventHandler) -> Result<()> {
+    let result = replay_file(path, speed, handler).await;
+    handler.flush_all().await;
+    result
+}
+
+async fn replay_file(path: &Path, speed: f64, handler: &mut EventHandler) -> Result<()> {
+    let file = File::open(path).with_context(|| format!("failed to open {:?}", path))?;
+    let start = Instant::now();
+    let mut first_timestamp = None;
+
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let record: Value = serde_json::from_str(&line)
+            .with_context(|| format!("failed to parse line {} of {:?}", index + 1, path))?;
+
+        if let Some(timestamp) = record_timestamp(&record) {
+            let first = *first_timestamp.get_or_insert(timestamp);
+            let offset_millis = (timestamp - first).max(0) as f64 / speed;
+            tokio::time::sleep_until(start + Duration::from_millis(offset_millis as u64)).await;
+        }
+
+        // Events wrapped by `--timestamps`/`--sequence` are unwrapped, so that they're not
+        // double-wrapped if those options are passed again during replay
+        let event = match record {
+            Value::Object(mut fields) if fields.contains_key("event") => {
+                fields.remove("event").unwrap_or_default()
+            }
+            other => other,
+        };
+
+        handler.handle_value(event).await?;
+    }
+
+    Ok(())
+}
+
+/// Timestamp of a recorded event in milliseconds, preferring the time it was received.
+fn record_timestamp(record: &Value) -> Option<i64> {
+    match record.get("receivedAt") {
+        Some(Value::Number(millis)) => millis.as_i64(),
+        Some(Value::String(timestamp)) => DateTime::parse_from_rfc3339(timestamp)
+            .ok()
+            .map(|timestamp| timestamp.timestamp_millis()),
+        _ => record
+            .get("event")
+            .unwrap_or(record)
+            .get("timestamp")
+            .and_then(Value::as_i64),
+    }
+}
+
+/// Sleeps until the deadline, or forever if there is no deadline.
+pub async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Returns the event type (e.g., `ModelMovedEvent`) of a serialized event.
+pub fn event_type(event: &Value) -> &str {
+    event
+        .get("messageType")
+        .and_then(Value::as_str)
+        .unwrap_or("UnknownEvent")
+}
+
+fn shell_command(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut cmd = Command::new("cmd");
+        cmd.arg("/C").arg(command);
+        cmd
+    } else {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(command);
+        cmd
+    }
+}
+
+/// Spawns the hook command without waiting for it, so that slow hooks don't delay the handling
+/// of subsequent events.
+fn spawn_hook(command: &str, event: &Value) -> Result<()> {
+    let mut cmd = shell_command(command);
+    cmd.stdin(Stdio::piped())
+        .env("VTS_EVENT_TYPE", event_type(event))
+        .env(
+            "VTS_EVENT_TIMESTAMP",
+            event
+                .get("timestamp")
+                .map(Value::to_string)
+                .unwrap_or_default(),
+        );
+
+    if let Some(Value::Object(data)) = event.get("data") {
+        for (key, value) in data {
+            let value = match value {
+                Value::String(s) => s.clone(),
+                Value::Number(n) => n.to_string(),
+                Value::Bool(b) => b.to_string(),
+                _ => continue,
+            };
+
+            cmd.env(format!("VTS_EVENT_DATA_{}", key.to_uppercase()), value);
+        }
+    }
+
+    let mut child = cmd
+        .spawn()
+        .with_context(|| format!("failed to spawn event hook `{}`", command))?;
+    let stdin = child.stdin.take();
+    let input = serde_json::to_vec(event)?;
+
+    tokio::spawn(async move {
+        if let Some(mut stdin) = stdin {
+            // The hook may exit without reading stdin, so a broken pipe here isn't fatal
+            if let Err(e) = stdin.write_all(&input).await {
+                warn!(error = %e, "Failed to write event to hook stdin");
+            }
+        }
+
+        match child.wait().await {
+            Ok(status) if !status.success() => warn!(%status, "Event hook exited unsuccessfully"),
+            Err(e) => error!(error = %e, "Failed to wait for event hook"),
+            _ => {}
+        }
+    });
+
+    Ok(())
+}
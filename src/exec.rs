@@ -0,0 +1,132 @@
+use crate::args::Command;
+use crate::{print_value, run_command, CAPTURED};
+
+use anyhow::{bail, Context, Result};
+use futures_util::stream::{FuturesOrdered, StreamExt};
+use serde_json::{json, Value};
+use std::cell::RefCell;
+use std::path::Path;
+use structopt::StructOpt;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, BufReader};
+use vtubestudio::Client;
+
+/// Runs commands read line-by-line from a file (or stdin, if the path is `-`), printing one line
+/// of output per command. Failed commands print an `{"error": ...}` object instead, and the
+/// remaining commands still run.
+///
+/// Up to `parallel` commands run at the same time, with their output printed in order.
+pub async fn run_file(client: &mut Client, path: &Path, parallel: usize) -> Result<()> {
+    if path == Path::new("-") {
+        run_lines(client, BufReader::new(tokio::io::stdin()), parallel).await
+    } else {
+        let file = tokio::fs::File::open(path)
+            .await
+            .with_context(|| format!("failed to open {:?}", path))?;
+        run_lines(client, BufReader::new(file), parallel).await
+    }
+}
+
+async fn run_lines(
+    client: &mut Client,
+    reader: impl AsyncBufRead + Unpin,
+    parallel: usize,
+) -> Result<()> {
+    let mut lines = reader.lines();
+    let mut running = FuturesOrdered::new();
+    let mut reading = true;
+    let mut failures = 0;
+
+    loop {
+        // Keep reading lines while waiting for results, so slow input (e.g., from stdin) doesn't
+        // hold back the output of commands that already finished
+        tokio::select! {
+            line = lines.next_line(), if reading && running.len() < parallel.max(1) => {
+                match line? {
+                    Some(line) => running.push_back(run_line(client.clone(), line)),
+                    None => reading = false,
+                }
+            }
+            Some(result) = running.next() => match result {
+                Ok(Some(value)) => print_value(&value)?,
+                Ok(None) => {}
+                Err(e) => {
+                    failures += 1;
+                    print_value(&json!({ "error": format!("{:#}", e) }))?;
+                }
+            },
+            else => break,
+        }
+    }
+
+    if failures > 0 {
+        bail!("{} command(s) failed", failures);
+    }
+
+    Ok(())
+}
+
+/// Runs a single line, returning `None` for blank lines and comments.
+async fn run_line(mut client: Client, line: String) -> Result<Option<Value>> {
+    match parse_line(&line)? {
+        Some(command) => run_captured(&mut client, command).await.map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Parses a line as either a command line (e.g., `hotkeys trigger --name "My Hotkey"`) or a JSON
+/// array of arguments. Returns `None` for blank lines and `#` comments.
+pub fn parse_line(line: &str) -> Result<Option<Command>> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+
+    let words: Vec<String> = if line.starts_with('[') {
+        serde_json::from_str(line).context("invalid JSON command (expected an array of strings)")?
+    } else {
+        shell_words::split(line).with_context(|| format!("invalid command `{}`", line))?
+    };
+
+    parse_words(words).map(Some)
+}
+
+/// Parses a command from its arguments (without the `vts` prefix).
+pub fn parse_words(words: Vec<String>) -> Result<Command> {
+    let command = Command::from_iter_safe(std::iter::once("vts".to_owned()).chain(words))?;
+    check_supported(&command)?;
+    Ok(command)
+}
+
+/// Whether the command can be run as one of a sequence of commands over a single connection.
+pub fn check_supported(command: &Command) -> Result<()> {
+    if command.is_stream() {
+        bail!("streaming commands can't be run here");
+    }
+
+    match command {
+        Command::Config(..) | Command::Discover { .. } | Command::Daemon | Command::Shell => {
+            bail!("`config`, `discover`, `daemon`, and `shell` commands can't be run here")
+        }
+        Command::FaceFound {
+            exit_code: true, ..
+        } => bail!("`face-found --exit-code` can't be run here"),
+        _ => Ok(()),
+    }
+}
+
+/// Runs a command, returning its output as a single value: the value itself if it printed one,
+/// an array if it printed several, or `null` if it printed nothing.
+pub async fn run_captured(client: &mut Client, command: Command) -> Result<Value> {
+    let mut values = CAPTURED
+        .scope(RefCell::new(Vec::new()), async {
+            Box::pin(run_command(client, command)).await?;
+            Ok::<_, anyhow::Error>(CAPTURED.with(|captured| captured.take()))
+        })
+        .await?;
+
+    Ok(match values.len() {
+        0 => Value::Null,
+        1 => values.remove(0),
+        _ => Value::Array(values),
+    })
+}
@@ -0,0 +1,161 @@
+use crate::args::{Command, Config};
+use crate::config::ConfigFile;
+use crate::{build_client, check_connection, print_value, run_command, CAPTURED, DRY_RUN};
+
+use anyhow::{bail, Result};
+use futures_util::future::join_all;
+use serde_json::{json, Map, Value};
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::path::Path;
+use tracing::info;
+use vtubestudio::ClientEvent;
+
+/// Connection settings given on the command line, applied to every host.
+pub struct Overrides<'a> {
+    pub url: Option<&'a str>,
+    pub host: Option<&'a str>,
+    pub port: Option<u16>,
+}
+
+/// Profiles (where `None` is the default profile) to run a command against with `--all-hosts`,
+/// `--instance`, or `--mirror`, or `None` if none of those were given.
+pub fn targets(
+    config_file: &ConfigFile,
+    profile: Option<&str>,
+    all_hosts: bool,
+    instances: &[String],
+    mirror: bool,
+) -> Result<Option<Vec<Option<String>>>> {
+    let profile_name = |name: &String| Some(name.clone()).filter(|name| name != "default");
+
+    Ok(if !instances.is_empty() {
+        Some(instances.iter().map(profile_name).collect())
+    } else if all_hosts || mirror {
+        let instances = &config_file.profile(profile)?.instances;
+        if instances.is_empty() {
+            bail!("no instances configured (set them with `vts config set instances <profiles>`)");
+        }
+
+        // `--mirror` also runs against the selected profile, while `--all-hosts` only runs against
+        // the listed instances
+        let selected = mirror.then(|| profile.map(str::to_owned));
+        Some(
+            selected
+                .into_iter()
+                .chain(instances.iter().map(profile_name))
+                .collect(),
+        )
+    } else {
+        None
+    })
+}
+
+/// Runs a command against the given profiles concurrently, and prints the output of each, keyed
+/// by `host:port`.
+pub async fn run(
+    config_path: &Path,
+    mut config_file: ConfigFile,
+    targets: Vec<Option<String>>,
+    overrides: &Overrides<'_>,
+    command: Command,
+) -> Result<()> {
+    if command.is_stream()
+        || matches!(
+            command,
+            Command::Config(..) | Command::Discover { .. } | Command::Daemon | Command::Shell
+        )
+    {
+        bail!("this command can't be run against multiple hosts");
+    }
+
+    // Profiles pointing at the same VTube Studio instance only need to run once
+    let mut seen = HashSet::new();
+    let mut hosts = Vec::new();
+    for name in targets {
+        let conf = config_file.profile(name.as_deref())?;
+        let url = conf.api_url(overrides.url, overrides.host, overrides.port);
+        if seen.insert(url.clone()) {
+            hosts.push((url, name, conf.decrypted()?));
+        }
+    }
+
+    let results = join_all(
+        hosts
+            .iter()
+            .map(|(url, _, conf)| run_on_host(conf, url, command.clone())),
+    )
+    .await;
+
+    let mut output = Map::new();
+    let mut failures = 0;
+    let mut updated_config = false;
+
+    for ((url, name, mut conf), (result, new_token)) in hosts.into_iter().zip(results) {
+        let value = match result {
+            Ok(mut values) if values.len() == 1 => values.remove(0),
+            Ok(values) if values.is_empty() => Value::Null,
+            Ok(values) => Value::Array(values),
+            Err(e) => {
+                failures += 1;
+                json!({ "error": format!("{:#}", e) })
+            }
+        };
+        // Keyed by `host:port`, or the full URL if it isn't a plain `ws://` one
+        let key = url.strip_prefix("ws://").unwrap_or(&url).to_owned();
+        output.insert(key, value);
+
+        if let Some(token) = new_token {
+            conf.token = Some(token);
+            config_file.set_profile(name.as_deref(), conf.encrypted()?);
+            updated_config = true;
+        }
+    }
+
+    if updated_config {
+        config_file.write(config_path)?;
+        info!(?config_path, "Wrote authentication tokens to config file");
+    }
+
+    print_value(&output)?;
+
+    if failures > 0 {
+        bail!("command failed on {} of {} hosts", failures, output.len());
+    }
+
+    Ok(())
+}
+
+/// Runs the command against a single host, returning its captured output and any new auth token.
+async fn run_on_host(
+    conf: &Config,
+    url: &str,
+    command: Command,
+) -> (Result<Vec<Value>>, Option<String>) {
+    // Nothing is sent in `--dry-run` mode, so there's no need to check the connection
+    if !matches!(command, Command::Wait { .. }) && !DRY_RUN.get().unwrap_or(&false) {
+        if let Err(e) = check_connection(url).await {
+            return (Err(e), None);
+        }
+    }
+
+    let (mut client, mut events) = build_client(conf, url);
+
+    let result = CAPTURED
+        .scope(RefCell::new(Vec::new()), async {
+            run_command(&mut client, command).await?;
+            Ok::<_, anyhow::Error>(CAPTURED.with(|captured| captured.take()))
+        })
+        .await;
+
+    drop(client);
+
+    let mut new_token = None;
+    while let Some(event) = events.next().await {
+        if let ClientEvent::NewAuthToken(token) = event {
+            new_token = Some(token);
+        }
+    }
+
+    (result, new_token)
+}
@@ -0,0 +1,73 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+/// Locations of the `StreamingAssets` directory (which contains the folders returned by
+/// `VtsFolderInfoRequest`), relative to the VTube Studio install directory.
+const STREAMING_ASSETS: &[&str] = &[
+    "VTube Studio_Data/StreamingAssets",
+    "VTube Studio.app/Contents/Resources/Data/StreamingAssets",
+];
+
+/// Finds the `StreamingAssets` directory, either from the given VTube Studio directory or from
+/// the usual Steam install locations.
+pub fn streaming_assets_dir(vts_dir: Option<&Path>) -> Option<PathBuf> {
+    let install_dirs = match vts_dir {
+        Some(dir) => vec![dir.to_path_buf()],
+        None => default_install_dirs(),
+    };
+
+    for dir in install_dirs {
+        if dir.ends_with("StreamingAssets") && dir.is_dir() {
+            return Some(dir);
+        }
+
+        for relative in STREAMING_ASSETS {
+            let path = dir.join(relative);
+            if path.is_dir() {
+                return Some(path);
+            }
+        }
+    }
+
+    None
+}
+
+fn default_install_dirs() -> Vec<PathBuf> {
+    let mut steam_dirs = Vec::new();
+
+    if cfg!(windows) {
+        steam_dirs.push(PathBuf::from(r"C:\Program Files (x86)\Steam"));
+        steam_dirs.push(PathBuf::from(r"C:\Program Files\Steam"));
+    }
+
+    if let Some(dirs) = directories::BaseDirs::new() {
+        let home = dirs.home_dir();
+
+        if cfg!(target_os = "macos") {
+            steam_dirs.push(home.join("Library/Application Support/Steam"));
+        } else if cfg!(unix) {
+            steam_dirs.push(home.join(".local/share/Steam"));
+            steam_dirs.push(home.join(".steam/steam"));
+        }
+    }
+
+    steam_dirs
+        .into_iter()
+        .map(|dir| dir.join("steamapps").join("common").join("VTube Studio"))
+        .collect()
+}
+
+/// Lists the names of the (non-hidden) files and directories in a folder, sorted by name.
+pub fn list_files(dir: &Path) -> Result<Vec<String>> {
+    let mut names = Vec::new();
+
+    for entry in std::fs::read_dir(dir).with_context(|| format!("failed to read {:?}", dir))? {
+        let name = entry?.file_name().to_string_lossy().into_owned();
+        if !name.starts_with('.') {
+            names.push(name);
+        }
+    }
+
+    names.sort();
+    Ok(names)
+}
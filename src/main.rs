@@ -1,183 +1,1210 @@
+mod animation;
 mod args;
+mod bench;
+mod cache;
+mod config;
+mod crypto;
+mod daemon;
+mod discover;
+mod events;
+mod exec;
+mod fanout;
+mod folders;
+mod output;
+mod proxy;
+mod query;
+mod script;
+mod shell;
 
 use crate::args::{
-    Args, ArtmeshesCommand, Command, Config, ConfigCommand, EventsCommand, ExpressionsCommand,
-    HotkeysCommand, ItemsCommand, ModelsCommand, NdiCommand, ParamsCommand, PhysicsCommand,
-    SetPhysicsCommand, StrengthOrWind,
+    Args, ArtmeshesCommand, Command, Config, ConfigCommand, EventType, EventsCommand,
+    ExpressionsCommand, FolderKind, FoldersArgs, FoldersCommand, HotkeysCommand, InitConfig,
+    InjectParam, ItemLoadOptions, ItemsCommand, ModelsCommand, NdiCommand, OscillateParam,
+    ParamsCommand, PermissionsCommand, PhysicsCommand, PostProcessingCommand, SetPhysicsCommand,
+    StrengthOrWind,
 };
+use crate::config::ConfigFile;
+use crate::events::EventHandler;
+use crate::output::{ErrorFormat, OutputFormat};
+use crate::proxy::Proxy;
+use crate::query::Query;
 
 use anyhow::{bail, Context, Result};
+use base64::Engine;
 use once_cell::sync::OnceCell;
 use serde::Serialize;
-use std::path::PathBuf;
+use serde_json::{json, Map, Value};
+use std::cell::RefCell;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::Duration;
 use structopt::StructOpt;
-use tracing::{error, info};
+use tower::{Service, ServiceExt};
+use tracing::{debug, error, info, warn};
 use vtubestudio::data::*;
-use vtubestudio::{Client, ClientEvent};
+use vtubestudio::{Client, ClientEvent, ClientEventStream};
+
+static OUTPUT_FILE: OnceCell<Mutex<File>> = OnceCell::new();
+static QUIET: OnceCell<bool> = OnceCell::new();
+static RAW: OnceCell<bool> = OnceCell::new();
+static REQUEST_TIMEOUT: OnceCell<Duration> = OnceCell::new();
+static CONNECT_TIMEOUT: OnceCell<Duration> = OnceCell::new();
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+static RETRIES: OnceCell<u32> = OnceCell::new();
+static KEEPALIVE: OnceCell<Duration> = OnceCell::new();
+static PROXY: OnceCell<Proxy> = OnceCell::new();
+static DRY_RUN: OnceCell<bool> = OnceCell::new();
+static RETRY_BACKOFF: OnceCell<Duration> = OnceCell::new();
+static INCLUDE_REQUEST: OnceCell<bool> = OnceCell::new();
+static TIMING: OnceCell<bool> = OnceCell::new();
 
 static JSON_COMPACT: OnceCell<bool> = OnceCell::new();
+static JSON_COLOR: OnceCell<bool> = OnceCell::new();
+static OUTPUT_FORMAT: OnceCell<OutputFormat> = OnceCell::new();
+static QUERY: OnceCell<Query> = OnceCell::new();
+static TEMPLATE: OnceCell<String> = OnceCell::new();
+
+tokio::task_local! {
+    /// Output values printed by the current task, if output is being captured.
+    static CAPTURED: RefCell<Vec<Value>>;
+
+    /// The current command's most recently sent request, for `--include-request` and `--timing`.
+    static LAST_SENT: RefCell<LastSent>;
+}
+
+/// A sent request and its timing, if enabled.
+#[derive(Clone, Default)]
+struct LastSent {
+    request: Option<Value>,
+    timing: Option<Value>,
+}
 
 #[tokio::main(flavor = "current_thread")]
-async fn main() -> Result<()> {
+async fn main() {
     let args = Args::from_args();
+    let error_format = args.error_format;
+
+    if let Err(e) = run(args).await {
+        // Not really an error, just the result of `face-found --exit-code`
+        if e.is::<FaceNotFound>() {
+            std::process::exit(1);
+        }
+
+        match error_format {
+            ErrorFormat::Text => eprintln!("Error: {:?}", e),
+            ErrorFormat::Json => eprintln!("{}", error_json(&e)),
+        }
+        std::process::exit(exit_code(&e));
+    }
+}
+
+async fn run(args: Args) -> Result<()> {
     let is_event_subscription = args.command.is_event_subscription();
-    let _ = JSON_COMPACT.set(args.compact || is_event_subscription);
+    if let Some(path) = &args.output {
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(args.append)
+            .truncate(!args.append)
+            .open(path)
+            .with_context(|| format!("failed to open output file {:?}", path))?;
+        let _ = OUTPUT_FILE.set(Mutex::new(file));
+    }
+    let _ = QUIET.set(args.quiet);
+    let _ = RAW.set(args.raw);
+    let _ = INCLUDE_REQUEST.set(args.include_request);
+    let _ = TIMING.set(args.timing);
+    let _ = JSON_COMPACT.set(args.compact || args.command.is_stream());
+    let _ = JSON_COLOR.set(args.color.enabled());
+    let _ = OUTPUT_FORMAT.set(args.format);
+    if let Some(query) = &args.query {
+        let _ = QUERY.set(query.clone());
+    }
+    if let Some(template) =
+        output::read_template(args.template.as_deref(), args.template_file.as_deref())?
+    {
+        let _ = TEMPLATE.set(template);
+    }
 
     tracing_subscriber::fmt::fmt().init();
 
+    if args.dry_run && !args.command.supports_dry_run() {
+        bail!("`--dry-run` can't be used with this command");
+    }
+
+    if let Command::Events(events) = &args.command {
+        if let EventsCommand::Replay { file, speed } = &events.command {
+            let mut handler = EventHandler::new(&events.options).await?;
+            return events::replay(file, speed.0, &mut handler).await;
+        }
+    }
+
+    if args.via_daemon {
+        exec::check_supported(&args.command)?;
+
+        let socket = args.daemon_socket.unwrap_or_else(daemon::default_socket);
+        for value in daemon::request(&socket, std::env::args().skip(1).collect()).await? {
+            print_value(&value)?;
+        }
+        return Ok(());
+    }
+
     let config_path = match args.config_file {
         Some(path) => path,
         None => {
-            let mut path =
-                directories::ProjectDirs::from("com.github", "walfie", "vtubestudio-cli")
-                    .context("failed to get base directory")?
-                    .config_dir()
-                    .to_path_buf();
+            let format = match &args.command {
+                Command::Config(ConfigCommand::Init(init)) => init.format,
+                _ => None,
+            };
+            config::default_path(format)?
+        }
+    };
+    let profile = args.profile.as_deref();
+
+    // Commands that don't connect to VTube Studio are handled here, so that everything after this
+    // only deals with commands that do
+    let command = match args.command {
+        Command::Discover {
+            timeout,
+            save,
+            scan,
+            ports,
+        } => {
+            let instances = match scan {
+                Some(subnet) => discover::scan(&subnet, &ports).await?,
+                None => discover::discover(timeout).await?,
+            };
+
+            if save {
+                let instance = instances
+                    .iter()
+                    .find(|instance| instance.active)
+                    .context("no active VTube Studio instances found")?;
+
+                let mut config_file = ConfigFile::read(&config_path)?;
+                let conf = config_file.profile_mut(profile)?;
+                conf.host = instance.host.clone();
+                conf.port = instance.port;
+                config_file.write(&config_path)?;
+                info!(?config_path, host = %instance.host, port = instance.port, "Updated config file");
+            }
 
-            path.push("config.json");
-            path
+            return print(&instances);
         }
+
+        Command::Config(command) => match command {
+            ConfigCommand::Init(init) => Connected::Init(init),
+            ConfigCommand::Reauth => Connected::Reauth,
+            ConfigCommand::Validate => {
+                let checks = config::validate(
+                    &config_path,
+                    profile,
+                    args.url.as_deref(),
+                    args.host.as_deref(),
+                    args.port,
+                )
+                .await;
+                print(&checks)?;
+
+                let failures = checks.iter().filter(|check| !check.passed).count();
+                if failures > 0 {
+                    bail!("{} check(s) failed", failures);
+                }
+                return Ok(());
+            }
+            ConfigCommand::Export { file } => {
+                ConfigFile::read(&config_path)?.decrypted()?.write(&file)?;
+                info!(?config_path, ?file, "Exported config file");
+                return Ok(());
+            }
+            ConfigCommand::Import { file, force } => {
+                if config_path.exists() && !force {
+                    bail!(
+                        "config file {:?} already exists (use `--force` to overwrite it)",
+                        config_path
+                    );
+                }
+
+                ConfigFile::read(&file)?.encrypted()?.write(&config_path)?;
+                info!(?config_path, ?file, "Imported config file");
+                return Ok(());
+            }
+            ConfigCommand::Show => {
+                return print(
+                    &ConfigFile::read(&config_path)?
+                        .profile(profile)?
+                        .decrypted()?,
+                );
+            }
+            ConfigCommand::Profiles => {
+                let config_file = ConfigFile::read(&config_path)?;
+                return print(&config_file.profiles.keys().collect::<Vec<_>>());
+            }
+            ConfigCommand::Get { key } => {
+                let conf = ConfigFile::read(&config_path)?
+                    .profile(profile)?
+                    .decrypted()?;
+                if let Some(value) = conf.get(&key)? {
+                    print_line(&value)?;
+                }
+                return Ok(());
+            }
+            ConfigCommand::Set { key, value } => {
+                let mut config_file = ConfigFile::read(&config_path)?;
+                let mut conf = config_file.profile(profile)?.decrypted()?;
+                conf.set(&key, &value)?;
+                config_file.set_profile(profile, conf.encrypted()?);
+                return config_file.write(&config_path);
+            }
+            ConfigCommand::Unset { key } => {
+                let mut config_file = ConfigFile::read(&config_path)?;
+                let mut conf = config_file.profile(profile)?.decrypted()?;
+                conf.unset(&key)?;
+                config_file.set_profile(profile, conf.encrypted()?);
+                return config_file.write(&config_path);
+            }
+            ConfigCommand::Path => {
+                return print_line(&format!("{:?}", config_path));
+            }
+        },
+
+        Command::Daemon => Connected::Daemon,
+        command => Connected::Command(command),
     };
 
-    let mut conf: Config = if let Command::Config(ConfigCommand::Init(conf)) = &args.command {
-        conf.clone()
+    let mut config_file = if let Connected::Init(init) = &command {
+        let conf = Config {
+            token: config::read_token(init.config.token.as_deref(), None)?,
+            ..init.config.clone()
+        };
+
+        // Keep any other profiles if the config file already exists
+        let mut config_file = if config_path.exists() {
+            ConfigFile::read(&config_path)?
+        } else {
+            ConfigFile::new(conf.encrypted()?)
+        };
+
+        config_file.set_profile(profile, conf.encrypted()?);
+        config_file
     } else {
-        let json_str = std::fs::read_to_string(&config_path).with_context(|| {
-            let bin = std::env::current_exe().unwrap_or_else(|_| PathBuf::from("vts"));
-            format!(
-                "failed to load config file from {:?} (try running `{:?} init` to create the file)",
-                &config_path, bin
-            )
-        })?;
+        ConfigFile::read(&config_path)?
+    };
 
-        serde_json::from_str(&json_str).context("failed to parse JSON from config file")?
+    let _ = CONNECT_TIMEOUT.set(args.connect_timeout);
+    let _ = RETRIES.set(args.retries);
+    let _ = KEEPALIVE.set(args.keepalive);
+    let _ = DRY_RUN.set(args.dry_run);
+    let _ = RETRY_BACKOFF.set(args.retry_backoff);
+    let request_timeout = match args.timeout {
+        Some(timeout) => Some(timeout),
+        None => config_file.profile(profile)?.request_timeout()?,
     };
+    if let Some(timeout) = request_timeout {
+        let _ = REQUEST_TIMEOUT.set(timeout);
+    }
 
-    let (mut client, mut events) = Client::builder()
-        .auth_token(conf.token.clone())
-        .authentication(
-            conf.plugin_name.clone(),
-            conf.plugin_developer.clone(),
-            None,
-        )
-        .build_tungstenite();
+    let targets = fanout::targets(
+        &config_file,
+        profile,
+        args.all_hosts,
+        &args.instance,
+        args.mirror,
+    )?;
+    if let Some(targets) = targets {
+        return match command {
+            Connected::Command(command) => {
+                let overrides = fanout::Overrides {
+                    url: args.url.as_deref(),
+                    host: args.host.as_deref(),
+                    port: args.port,
+                };
+                fanout::run(&config_path, config_file, targets, &overrides, command).await
+            }
+            _ => bail!("this command can't be run against multiple hosts"),
+        };
+    }
 
-    match args.command {
-        Command::Config(command) => {
-            use ConfigCommand::*;
+    let mut conf: Config = config_file.profile(profile)?.decrypted()?;
+
+    // A token given on the command line is only used for this run, and never saved
+    let token_override = match &command {
+        Connected::Init(..) | Connected::Reauth => None,
+        _ => config::read_token(args.token.as_deref(), args.token_file.as_deref())?,
+    };
+
+    // Overrides from the command line only apply to this run, and aren't saved to the config
+    let url = conf.api_url(args.url.as_deref(), args.host.as_deref(), args.port);
+    let plugin_name = args
+        .plugin_name
+        .clone()
+        .unwrap_or_else(|| conf.plugin_name.clone());
+    let plugin_developer = args
+        .plugin_developer
+        .clone()
+        .unwrap_or_else(|| conf.plugin_developer.clone());
+
+    let token = match &command {
+        // Discard the existing token so that a new one is requested
+        Connected::Reauth => None,
+        _ => token_override
+            .clone()
+            .or_else(|| conf.token_for(&plugin_name, &plugin_developer).cloned()),
+    };
+
+    let client_conf = Config {
+        token,
+        plugin_name: plugin_name.clone(),
+        plugin_developer: plugin_developer.clone(),
+        ..conf.clone()
+    };
+    // Nothing is sent in `--dry-run` mode, so there's no need to connect
+    let connects = !args.dry_run;
+
+    if !args.no_cache {
+        cache::init(&url);
+    }
+
+    let client_url = if connects {
+        proxied_url(args.proxy.clone(), &url).await?
+    } else {
+        url.clone()
+    };
+    let (mut client, mut events) = build_client(&client_conf, &client_url);
+
+    if connects {
+        match (&command, args.wait_for_api) {
+            // Waits for the connection itself
+            (Connected::Command(Command::Wait { .. }), _) => {}
+            (_, Some(timeout)) => {
+                wait_for_api(&mut client, timeout).await?;
+            }
+            (_, None) => check_connection(&url).await?,
+        }
+    }
+
+    let mut event_handler = match &command {
+        Connected::Command(Command::Events(events)) if events.command.is_subscription() => {
+            Some(EventHandler::new(&events.options).await?)
+        }
+        _ => None,
+    };
+
+    let mut subscriptions = Vec::new();
+    let mut failure = None;
 
-            match command {
-                Init(..) => {
-                    info!("Requesting plugin permissions. Please accept the permissions pop-up in the VTube Studio app.");
+    match command {
+        Connected::Init(init) => {
+            info!("Requesting plugin permissions. Please accept the permissions pop-up in the VTube Studio app.");
+
+            match init.timeout {
+                Some(timeout) => {
+                    tokio::time::timeout(timeout, client.send(&StatisticsRequest {}))
+                        .await
+                        .with_context(|| {
+                            format!("permissions pop-up was not accepted within {:?}", timeout)
+                        })??;
+                }
+                None => {
                     client.send(&StatisticsRequest {}).await?;
                 }
-                Show => {
-                    print(&conf)?;
+            }
+        }
+
+        Connected::Reauth => {
+            info!("Requesting a new auth token. Please accept the permissions pop-up in the VTube Studio app.");
+            client.send(&StatisticsRequest {}).await?;
+        }
+
+        Connected::Daemon => {
+            let socket = args.daemon_socket.unwrap_or_else(daemon::default_socket);
+            daemon::serve(&mut client, &socket).await?;
+        }
+
+        Connected::Command(command) => {
+            let result = match run_command(&mut client, command.clone()).await {
+                // The stored token may have been revoked (e.g., the plugin was removed in VTube
+                // Studio), so request a new one and retry once
+                Err(e)
+                    if token_override.is_none()
+                        && client_conf.token.is_some()
+                        && is_token_rejected(&e) =>
+                {
+                    warn!(error = %e, "Auth token was rejected. Requesting a new one; please accept the permissions pop-up in the VTube Studio app.");
+
+                    let reauth_conf = Config {
+                        token: None,
+                        ..client_conf.clone()
+                    };
+                    (client, events) = build_client(&reauth_conf, &client_url);
+                    run_command(&mut client, command).await
+                }
+                result => result,
+            };
+
+            // Handle client events (e.g., saving a new token) before exiting with the status
+            subscriptions = match result {
+                Err(e) if e.is::<FaceNotFound>() => {
+                    failure = Some(e);
+                    Vec::new()
+                }
+                result => result?,
+            };
+        }
+    };
+
+    if args.dry_run {
+        return Ok(());
+    }
+
+    let mut client = if is_event_subscription {
+        Some(client)
+    } else {
+        drop(client);
+        None
+    };
+
+    let mut next_ping = next_keepalive().filter(|_| client.is_some());
+    let mut lost_connection = None;
+
+    loop {
+        let deadline = event_handler.as_ref().and_then(EventHandler::next_deadline);
+
+        let client_event = tokio::select! {
+            client_event = events.next() => match client_event {
+                Some(client_event) => client_event,
+                None => break,
+            },
+            _ = events::sleep_until(deadline) => {
+                if let Some(handler) = &mut event_handler {
+                    handler.flush_due().await;
+                }
+                continue;
+            }
+            _ = events::sleep_until(next_ping) => {
+                // A crashed VTube Studio may not close the connection, so check that it's alive
+                if let (Some(client), Some(handler)) = (&mut client, &event_handler) {
+                    if let Err(e) = ping(client).await {
+                        if !handler.should_reconnect() {
+                            lost_connection = Some(e);
+                            break;
+                        }
+
+                        warn!(error = %format!("{:#}", e), "Keepalive failed");
+                        events::resubscribe(client, &subscriptions).await;
+                    }
+                }
+                next_ping = next_keepalive();
+                continue;
+            }
+        };
+
+        match client_event {
+            ClientEvent::NewAuthToken(token) => {
+                if token_override.is_some() {
+                    warn!("Received a new auth token, but not saving it since a token was provided via `--token` or `--token-file`");
+                    continue;
+                }
+
+                conf.set_token_for(&plugin_name, &plugin_developer, token);
+                config_file.set_profile(profile, conf.encrypted()?);
+                config_file.write(&config_path)?;
+                info!(?config_path, "Wrote authentication token to config file");
+            }
+
+            ClientEvent::Disconnected => {
+                if let (Some(client), Some(handler)) = (&mut client, &event_handler) {
+                    if !handler.should_reconnect() {
+                        info!("Disconnected from VTube Studio");
+                        break;
+                    }
+
+                    events::resubscribe(client, &subscriptions).await;
                 }
-                Path => {
-                    println!("{:?}", config_path);
+            }
+
+            ClientEvent::Api(event) => {
+                if let Some(handler) = &mut event_handler {
+                    if let Err(e) = handler.handle(&event).await {
+                        error!(error = %e, "Failed to handle event");
+                    }
                 }
             }
+
+            _ => {}
+        }
+    }
+
+    if let Some(handler) = &mut event_handler {
+        handler.flush_all().await;
+    }
+
+    if let Some(e) = lost_connection {
+        return Err(e.context("lost connection to VTube Studio"));
+    }
+
+    failure.map_or(Ok(()), Err)
+}
+
+/// A command that connects to VTube Studio. Commands that don't (e.g., `discover`, or most
+/// `config` subcommands) are handled before connecting.
+#[allow(clippy::large_enum_variant)]
+enum Connected {
+    /// `config init`
+    Init(InitConfig),
+    /// `config reauth`
+    Reauth,
+    Daemon,
+    Command(Command),
+}
+
+/// Stops a command after its first request is printed, in `--dry-run` mode.
+#[derive(Debug)]
+struct DryRun;
+
+impl std::fmt::Display for DryRun {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("stopped after printing the request (dry run)")
+    }
+}
+
+impl std::error::Error for DryRun {}
+
+/// Returned by `face-found --exit-code` when no face is found, to exit with status 1.
+#[derive(Debug)]
+struct FaceNotFound;
+
+impl std::fmt::Display for FaceNotFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("face not found")
+    }
+}
+
+impl std::error::Error for FaceNotFound {}
+
+/// Runs a command that requires a connection to VTube Studio, returning any event subscriptions.
+async fn run_command(
+    client: &mut Client,
+    command: Command,
+) -> Result<Vec<EventSubscriptionRequest>> {
+    // Scoped per command, since commands can run concurrently (e.g., with `exec --parallel`)
+    let result = LAST_SENT
+        .scope(RefCell::default(), dispatch_command(client, command))
+        .await;
+
+    match result {
+        Err(e) if e.is::<DryRun>() => Ok(Vec::new()),
+        result => result,
+    }
+}
+
+async fn dispatch_command(
+    client: &mut Client,
+    command: Command,
+) -> Result<Vec<EventSubscriptionRequest>> {
+    match command {
+        Command::Config(..) | Command::Discover { .. } | Command::Daemon => {
+            bail!("`config`, `discover`, and `daemon` commands can't be used here");
+        }
+
+        Command::Exec { file, parallel } => {
+            exec::run_file(client, &file, parallel).await?;
+        }
+
+        Command::Run { script } => {
+            script::run_file(client, &script).await?;
+        }
+
+        Command::Shell => {
+            shell::run(client).await?;
         }
 
         Command::State => {
-            print(&client.send(&ApiStateRequest {}).await?)?;
+            print(&send(client, &ApiStateRequest {}).await?)?;
+        }
+
+        Command::Ping { timeout } => {
+            let started = std::time::Instant::now();
+            let state = tokio::time::timeout(timeout, send(client, &ApiStateRequest {}))
+                .await
+                .with_context(|| format!("VTube Studio didn't respond within {:?}", timeout))??;
+
+            if !*QUIET.get().unwrap_or(&false) {
+                let state = serde_json::to_value(state)?;
+                print_line(&format!(
+                    "VTube Studio {} responded in {:.1} ms",
+                    state["vTubeStudioVersion"]
+                        .as_str()
+                        .unwrap_or("(unknown version)"),
+                    started.elapsed().as_secs_f64() * 1000.0
+                ))?;
+            }
         }
 
-        Command::Folders => {
-            print(&client.send(&VtsFolderInfoRequest {}).await?)?;
+        Command::Raw { message_type, data } => {
+            send_raw_request(client, message_type, data.as_deref()).await?;
+        }
+
+        Command::Bench {
+            count,
+            concurrency,
+            request,
+        } => {
+            print(&bench::run(client, request, count, concurrency).await?)?;
+        }
+
+        Command::Wait { timeout } => {
+            print(&wait_for_api(client, timeout).await?)?;
+        }
+
+        Command::Folders(args) => {
+            handle_folders_command(client, args).await?;
         }
 
         Command::Stats => {
-            print(&client.send(&StatisticsRequest {}).await?)?;
+            print(&send(client, &StatisticsRequest {}).await?)?;
         }
 
         Command::SceneColors => {
-            print(&client.send(&SceneColorOverlayInfoRequest {}).await?)?;
+            print(&send(client, &SceneColorOverlayInfoRequest {}).await?)?;
         }
 
-        Command::FaceFound => {
-            print(&client.send(&FaceFoundRequest {}).await?)?;
+        Command::FaceFound { exit_code, quiet } => {
+            let resp = send(client, &FaceFoundRequest {}).await?;
+
+            if !quiet {
+                print(&resp)?;
+            }
+
+            if exit_code && !resp.found {
+                return Err(FaceNotFound.into());
+            }
         }
 
         Command::Params(command) => {
-            handle_params_command(&mut client, command).await?;
+            handle_params_command(client, command).await?;
         }
 
         Command::Hotkeys(command) => {
-            handle_hotkeys_command(&mut client, command).await?;
+            handle_hotkeys_command(client, command).await?;
         }
 
         Command::Artmeshes(command) => {
-            handle_artmeshes_command(&mut client, command).await?;
+            handle_artmeshes_command(client, command).await?;
         }
 
         Command::Models(command) => {
-            handle_models_command(&mut client, command).await?;
+            handle_models_command(client, command).await?;
         }
 
         Command::Expressions(command) => {
-            handle_expressions_command(&mut client, command).await?;
+            handle_expressions_command(client, command).await?;
         }
 
         Command::Ndi(command) => {
-            handle_ndi_command(&mut client, command).await?;
+            handle_ndi_command(client, command).await?;
         }
 
         Command::Physics(command) => {
-            handle_physics_command(&mut client, command).await?;
+            handle_physics_command(client, command).await?;
         }
 
         Command::Items(command) => {
-            handle_items_command(&mut client, command).await?;
+            handle_items_command(client, command).await?;
+        }
+
+        Command::PostProcessing(command) => {
+            handle_post_processing_command(client, command).await?;
+        }
+
+        Command::Permissions(command) => {
+            handle_permissions_command(client, command).await?;
+        }
+
+        Command::Events(events) => {
+            return handle_events_command(client, events.command).await;
+        }
+    }
+
+    Ok(Vec::new())
+}
+
+/// Checks that VTube Studio's API port is reachable, so that a wrong host/port (or VTube Studio
+/// not running) gives a clear error instead of a request failure.
+async fn check_connection(url: &str) -> Result<()> {
+    let timeout = *CONNECT_TIMEOUT.get().unwrap_or(&DEFAULT_CONNECT_TIMEOUT);
+    let (host, port) = config::socket_address(url)?;
+
+    let mut attempt = 0;
+    loop {
+        let connect = async {
+            match PROXY.get() {
+                Some(proxy) => {
+                    proxy::connect(proxy, &host, port).await?;
+                }
+                None => {
+                    tokio::net::TcpStream::connect((host.as_str(), port)).await?;
+                }
+            }
+            Ok::<_, anyhow::Error>(())
+        };
+
+        let error = match tokio::time::timeout(timeout, connect).await {
+            Ok(Ok(())) => return Ok(()),
+            Ok(Err(e)) => e.context(format!(
+                "could not connect to {} (is VTube Studio running with the API enabled?)",
+                url
+            )),
+            Err(_) => anyhow::anyhow!("timed out after {:?} connecting to {}", timeout, url),
+        };
+
+        // Connection failures are always worth retrying (e.g., VTube Studio is still starting)
+        match retry_delay(attempt) {
+            Some(delay) => {
+                warn!(error = %error, ?delay, "Retrying connection");
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            None => return Err(error),
+        }
+    }
+}
+
+/// Polls VTube Studio until it responds to an API state request, reconnecting as needed. Fails
+/// if it hasn't responded within the timeout.
+async fn wait_for_api(client: &mut Client, timeout: Duration) -> Result<ApiStateResponse> {
+    const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+    let deadline = tokio::time::Instant::now() + timeout;
+    let mut last_error = None;
+    info!(?timeout, "Waiting for the VTube Studio API");
+
+    loop {
+        match tokio::time::timeout_at(deadline, client.send(&ApiStateRequest {})).await {
+            Ok(Ok(state)) => return Ok(state),
+            Ok(Err(e)) => {
+                debug!(error = %e, "VTube Studio API isn't ready yet");
+                last_error = Some(e);
+            }
+            Err(_) => {}
         }
 
-        Command::Events(command) => {
-            handle_events_command(&mut client, command).await?;
+        if tokio::time::Instant::now() + POLL_INTERVAL >= deadline {
+            let message = format!("VTube Studio API wasn't ready within {:?}", timeout);
+            return Err(match last_error {
+                Some(e) => anyhow::Error::new(e).context(message),
+                None => anyhow::Error::msg(message),
+            });
         }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// When the next keepalive request is due, or `None` if disabled with `--keepalive 0s`.
+fn next_keepalive() -> Option<tokio::time::Instant> {
+    KEEPALIVE
+        .get()
+        .filter(|interval| !interval.is_zero())
+        .map(|interval| tokio::time::Instant::now() + *interval)
+}
+
+/// Checks that the connection is still alive, failing if VTube Studio doesn't respond to an API
+/// state request within the `--keepalive` interval.
+async fn ping(client: &mut Client) -> Result<()> {
+    let timeout = *KEEPALIVE.get().unwrap_or(&DEFAULT_CONNECT_TIMEOUT);
+
+    match tokio::time::timeout(timeout, client.send(&ApiStateRequest {})).await {
+        Ok(result) => result.map(drop).context("keepalive request failed"),
+        Err(_) => bail!(
+            "VTube Studio didn't respond to a keepalive request within {:?}",
+            timeout
+        ),
+    }
+}
+
+/// Keeps the connection open for a duration (e.g., so that a tint or item stays active), failing
+/// early if the connection is lost.
+async fn hold_connection(client: &mut Client, duration: Duration) -> Result<()> {
+    let end = tokio::time::Instant::now() + duration;
+    let mut next_ping = next_keepalive();
+
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep_until(end) => return Ok(()),
+            _ = events::sleep_until(next_ping) => {
+                ping(client).await.context("lost connection to VTube Studio")?;
+                next_ping = next_keepalive();
+            }
+        }
+    }
+}
+
+/// With a proxy (from `--proxy`, or the environment for `ws://` URLs), starts a local tunnel
+/// through it and returns the URL to connect to instead, since the WebSocket client only
+/// connects directly. Otherwise, returns the URL unchanged.
+async fn proxied_url(proxy: Option<Proxy>, url: &str) -> Result<String> {
+    let (host, port) = config::socket_address(url)?;
+    let mut local_url = url::Url::parse(url)?;
+    let is_ws = local_url.scheme() == "ws";
+
+    let proxy = match proxy {
+        Some(_) if !is_ws => bail!("proxies are only supported for `ws://` URLs"),
+        Some(proxy) => proxy,
+        None => match proxy::from_env(&host)? {
+            Some(proxy) if is_ws => proxy,
+            _ => return Ok(url.to_owned()),
+        },
     };
 
-    if !is_event_subscription {
-        drop(client);
+    let _ = PROXY.set(proxy.clone());
+    let local_port = proxy::tunnel(proxy, host, port).await?;
+    local_url
+        .set_host(Some("127.0.0.1"))
+        .context("failed to set tunnel host")?;
+    let _ = local_url.set_port(Some(local_port));
+
+    Ok(local_url.to_string())
+}
+
+/// How long to wait before the next retry (doubling `--retry-backoff` each attempt), or `None`
+/// if there are no `--retries` left.
+fn retry_delay(attempt: u32) -> Option<Duration> {
+    if attempt >= *RETRIES.get().unwrap_or(&0) {
+        return None;
     }
 
-    while let Some(client_event) = events.next().await {
-        match client_event {
-            ClientEvent::NewAuthToken(token) => {
-                conf.token = Some(token);
+    let backoff = *RETRY_BACKOFF.get().unwrap_or(&Duration::ZERO);
+    Some(backoff.saturating_mul(2u32.saturating_pow(attempt)))
+}
 
-                let mut base_path = config_path.clone();
-                base_path.pop();
-                std::fs::create_dir_all(&base_path)
-                    .with_context(|| format!("Failed to create directory {:?}", base_path))?;
+/// Whether a failed request can be sent again: if the connection couldn't be made, or if it was
+/// dropped and the request is safe to repeat. Other errors (e.g., API errors, or invalid
+/// responses) would most likely fail again.
+fn is_retryable(error: &anyhow::Error, message_type: &EnumString<RequestType>) -> bool {
+    let error = match error.downcast_ref::<vtubestudio::Error>() {
+        Some(error) => error,
+        None => return false,
+    };
 
-                if let Err(e) = std::fs::write(&config_path, serde_json::to_string_pretty(&conf)?) {
-                    error!(?config_path, "Failed to write config file");
-                    anyhow::bail!(e);
-                } else {
-                    info!(?config_path, "Wrote authentication token to config file");
-                }
+    error.has_kind(vtubestudio::ErrorKind::ConnectionRefused)
+        || (error.has_kind(vtubestudio::ErrorKind::ConnectionDropped)
+            && is_idempotent(message_type))
+}
+
+/// Whether sending a request twice has the same effect as sending it once. If the connection
+/// drops after sending a request, VTube Studio may have already received it, so requests that
+/// aren't idempotent (e.g., triggering a hotkey, or loading an item) aren't retried.
+fn is_idempotent(message_type: &EnumString<RequestType>) -> bool {
+    ![
+        RequestType::AuthenticationTokenRequest,
+        RequestType::HotkeyTriggerRequest,
+        RequestType::ItemLoadRequest,
+        RequestType::ParameterCreationRequest,
+        RequestType::PermissionRequest,
+    ]
+    .into_iter()
+    .any(|request_type| *message_type == request_type)
+}
+
+fn build_client(conf: &Config, url: &str) -> (Client, ClientEventStream) {
+    Client::builder()
+        .url(url)
+        .auth_token(conf.token.clone())
+        .authentication(
+            conf.plugin_name.clone(),
+            conf.plugin_developer.clone(),
+            None,
+        )
+        .build_tungstenite()
+}
+
+/// Exit code for an error. VTube Studio API errors map to `100 + error ID / 50`, so that each
+/// range of error IDs (e.g., token requests, model loading) gets its own exit code.
+fn exit_code(error: &anyhow::Error) -> i32 {
+    error
+        .downcast_ref::<vtubestudio::Error>()
+        .and_then(|e| e.to_api_error())
+        .map_or(1, |e| (100 + e.error_id.as_i32() / 50).clamp(100, 255))
+}
+
+/// Error as a JSON object, for `--error-format json`.
+fn error_json(error: &anyhow::Error) -> Value {
+    let api_error = error
+        .downcast_ref::<vtubestudio::Error>()
+        .and_then(|e| e.to_api_error());
+
+    json!({
+        "message": format!("{:#}", error),
+        "errorID": api_error.map(|e| e.error_id.as_i32()),
+        "apiMessage": api_error.map(|e| e.message.clone()),
+        "exitCode": exit_code(error),
+        "command": redacted_args(),
+    })
+}
+
+/// Command line arguments, with any `--token` value hidden.
+fn redacted_args() -> Vec<String> {
+    let mut redact_next = false;
+
+    std::env::args()
+        .skip(1)
+        .map(|arg| {
+            if std::mem::take(&mut redact_next) {
+                "<redacted>".to_owned()
+            } else if arg.starts_with("--token=") {
+                "--token=<redacted>".to_owned()
+            } else {
+                redact_next = arg == "--token";
+                arg
             }
+        })
+        .collect()
+}
+
+/// Returns whether the error is from VTube Studio rejecting the auth token.
+fn is_token_rejected(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<vtubestudio::Error>()
+        .is_some_and(|e| e.is_unauthenticated_error())
+}
+
+/// Sends a request, retrying transient failures if `--retries` is set. In `--dry-run` mode, the
+/// request is printed instead, and the command stops.
+async fn send<Req: Request>(client: &mut Client, req: &Req) -> Result<Req::Response> {
+    check_dry_run(req)?;
+
+    let mut attempt = 0;
+    loop {
+        match send_once(client, req).await {
+            Err(e) if is_retryable(&e, &Req::MESSAGE_TYPE) => match retry_delay(attempt) {
+                Some(delay) => {
+                    warn!(error = %format!("{:#}", e), ?delay, "Retrying request");
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                None => return Err(e),
+            },
+            result => return result,
+        }
+    }
+}
+
+/// In `--dry-run` mode, prints the request and returns a `DryRun` error to stop the command.
+fn check_dry_run<Req: Request>(req: &Req) -> Result<()> {
+    if *DRY_RUN.get().unwrap_or(&false) {
+        print_value(&serde_json::to_value(RequestEnvelope::new(req)?)?)?;
+        return Err(DryRun.into());
+    }
+
+    Ok(())
+}
+
+/// Sends a request once. With `--raw`, the response envelope is printed exactly as received.
+async fn send_once<Req: Request>(client: &mut Client, req: &Req) -> Result<Req::Response> {
+    if *INCLUDE_REQUEST.get().unwrap_or(&false) {
+        let envelope = serde_json::to_value(RequestEnvelope::new(req)?)?;
+        let _ = LAST_SENT.try_with(|last| last.borrow_mut().request = Some(envelope));
+    }
+
+    let raw = *RAW.get().unwrap_or(&false);
+    let timing = *TIMING.get().unwrap_or(&false);
+    if !raw && !timing {
+        return with_timeout(client.send(req)).await;
+    }
+
+    let sent_at = chrono::Utc::now();
+    let started = std::time::Instant::now();
+    let resp = with_timeout(send_envelope(client, RequestEnvelope::new(req)?)).await?;
+
+    if timing {
+        let timing = json!({
+            "latencyMs": started.elapsed().as_secs_f64() * 1000.0,
+            "serverTimestamp": resp.timestamp,
+            "serverTimestampDeltaMs": resp.timestamp - sent_at.timestamp_millis(),
+        });
+        let _ = LAST_SENT.try_with(|last| last.borrow_mut().timing = Some(timing));
+    }
+
+    if raw {
+        print_value(&with_metadata(serde_json::to_value(&resp)?))?;
+    }
+    Ok(resp.parse::<Req::Response>()?)
+}
+
+/// Sends a request envelope through the client's underlying service, returning the response
+/// envelope as received (which may contain an API error).
+async fn send_envelope(
+    client: &Client,
+    envelope: RequestEnvelope,
+) -> Result<ResponseEnvelope, vtubestudio::Error> {
+    let mut service = client.clone().into_service();
+    service.ready().await?.call(envelope).await
+}
+
+/// Sends a request with an arbitrary message type and data, printing the response envelope.
+async fn send_raw_request(
+    client: &mut Client,
+    message_type: String,
+    data: Option<&str>,
+) -> Result<()> {
+    let data: Value = match data {
+        None => json!({}),
+        Some("-") => serde_json::from_reader(std::io::stdin()).context("invalid JSON on stdin")?,
+        Some(data) => serde_json::from_str(data).context("invalid JSON for `--data`")?,
+    };
+
+    let envelope = RequestEnvelope {
+        message_type: EnumString::new_from_str(message_type),
+        data: OpaqueValue::new(&data)?,
+        ..RequestEnvelope::default()
+    };
+
+    if *DRY_RUN.get().unwrap_or(&false) {
+        return print_value(&serde_json::to_value(&envelope)?);
+    }
+
+    let resp: ResponseEnvelope = with_timeout(send_envelope(client, envelope)).await?;
+    print_value(&serde_json::to_value(&resp)?)
+}
+
+/// Applies the `--timeout` (or config `timeout`) to a request, if set.
+async fn with_timeout<T>(
+    request: impl std::future::Future<Output = Result<T, vtubestudio::Error>>,
+) -> Result<T> {
+    match REQUEST_TIMEOUT.get() {
+        Some(timeout) => match tokio::time::timeout(*timeout, request).await {
+            Ok(result) => Ok(result?),
+            Err(_) => bail!("VTube Studio didn't respond within {:?}", timeout),
+        },
+        None => Ok(request.await?),
+    }
+}
+
+/// Prints a response. With `--raw`, this does nothing, since responses are printed by `send`.
+fn print<T: Serialize>(value: &T) -> Result<()> {
+    if *RAW.get().unwrap_or(&false) {
+        return Ok(());
+    }
+
+    print_value(&with_metadata(serde_json::to_value(value)?))
+}
+
+/// With `--include-request` or `--timing`, combines the response with the most recently sent
+/// request and/or its timing.
+fn with_metadata(response: Value) -> Value {
+    let include_request = *INCLUDE_REQUEST.get().unwrap_or(&false);
+    let timing = *TIMING.get().unwrap_or(&false);
+    if !include_request && !timing {
+        return response;
+    }
+
+    let last = LAST_SENT
+        .try_with(|last| last.borrow().clone())
+        .unwrap_or_default();
+
+    let mut object = Map::new();
+    if include_request {
+        object.insert("request".to_owned(), last.request.unwrap_or(Value::Null));
+    }
+    if timing {
+        object.insert("timing".to_owned(), last.timing.unwrap_or(Value::Null));
+    }
+    object.insert("response".to_owned(), response);
+    Value::Object(object)
+}
+
+fn print_value<T: Serialize>(value: &T) -> Result<()> {
+    // When output is being captured (e.g., for `--all-hosts`), collect values instead
+    let captured = CAPTURED.try_with(|captured| -> Result<()> {
+        captured.borrow_mut().push(serde_json::to_value(value)?);
+        Ok(())
+    });
+    if let Ok(result) = captured {
+        return result;
+    }
+
+    if *QUIET.get().unwrap_or(&false) {
+        return Ok(());
+    }
+
+    let mut value = serde_json::to_value(value)?;
+    if let Some(query) = QUERY.get() {
+        value = query.apply(value);
+    }
+
+    if let Some(template) = TEMPLATE.get() {
+        return print_line(&output::render_template(template, &value)?);
+    }
+
+    let compact = *JSON_COMPACT.get().unwrap_or(&false);
+    let string = match OUTPUT_FORMAT.get().unwrap_or(&OutputFormat::Json) {
+        OutputFormat::Json if *JSON_COLOR.get().unwrap_or(&false) => {
+            output::to_colored_json(&value, !compact)
+        }
+        OutputFormat::Json if compact => serde_json::to_string(&value)?,
+        OutputFormat::Json => serde_json::to_string_pretty(&value)?,
+        OutputFormat::Table => output::to_table(&value),
+        OutputFormat::Logfmt => output::to_logfmt(&value),
+        format => output::to_delimited(&value, *format),
+    };
+
+    print_line(&string)
+}
+
+/// Writes a line to the `--output` file if one was given, or stdout otherwise.
+fn print_line(line: &str) -> Result<()> {
+    match OUTPUT_FILE.get() {
+        Some(file) => {
+            let mut file = file.lock().unwrap_or_else(|e| e.into_inner());
+            writeln!(file, "{}", line).context("failed to write to output file")?;
+            file.flush().context("failed to write to output file")
+        }
+        None => {
+            println!("{}", line);
+            Ok(())
+        }
+    }
+}
 
-            ClientEvent::Api(event) => {
-                let _ = print(&event);
+async fn handle_folders_command(client: &mut Client, args: FoldersArgs) -> Result<()> {
+    let resp = send(client, &VtsFolderInfoRequest {}).await?;
+    let base_dir = folders::streaming_assets_dir(args.vts_dir.as_deref());
+
+    match args.command {
+        None => {
+            let mut value = serde_json::to_value(&resp)?;
+
+            if let (Some(base_dir), Some(map)) = (&base_dir, value.as_object_mut()) {
+                let paths = map
+                    .iter()
+                    .filter_map(|(key, folder)| {
+                        let path = base_dir.join(folder.as_str()?);
+                        Some((key.clone(), Value::String(path.display().to_string())))
+                    })
+                    .collect::<Map<String, Value>>();
+
+                map.insert("absolutePaths".to_owned(), paths.into());
             }
 
-            _ => {}
+            print(&value)?;
         }
-    }
 
-    Ok(())
-}
+        Some(FoldersCommand::Ls { folder }) => {
+            let base_dir = base_dir.context(
+                "could not find the VTube Studio install directory (try passing `--vts-dir`)",
+            )?;
 
-fn print<T: Serialize>(value: &T) -> Result<()> {
-    let string = if *JSON_COMPACT.get().unwrap_or(&false) {
-        serde_json::to_string(value)?
-    } else {
-        serde_json::to_string_pretty(value)?
-    };
+            let folder_name = match folder {
+                FolderKind::Models => resp.models,
+                FolderKind::Items => resp.items,
+                FolderKind::Backgrounds => resp.backgrounds,
+            };
+
+            print(&folders::list_files(&base_dir.join(folder_name))?)?;
+        }
+    }
 
-    println!("{}", string);
     Ok(())
 }
 
@@ -186,61 +1213,174 @@ async fn handle_params_command(client: &mut Client, command: ParamsCommand) -> R
 
     match command {
         Create(req) => {
-            let resp = client
-                .send(&ParameterCreationRequest {
+            let resp = send(
+                client,
+                &ParameterCreationRequest {
                     parameter_name: req.name,
                     explanation: req.explanation,
                     min: req.min,
                     max: req.max,
                     default_value: req.default,
-                })
-                .await?;
+                },
+            )
+            .await?;
 
             print(&resp)?;
         }
 
         Get { name } => {
-            print(&client.send(&ParameterValueRequest { name }).await?)?;
+            print(&send(client, &ParameterValueRequest { name }).await?)?;
+        }
+
+        Watch {
+            name,
+            interval,
+            changes_only,
+        } => {
+            let req = ParameterValueRequest { name };
+            let mut resp = send(client, &req).await?;
+            print(&resp)?;
+
+            loop {
+                tokio::time::sleep(interval).await;
+
+                let next = send(client, &req).await?;
+                if !changes_only || next.0.value != resp.0.value {
+                    print(&next)?;
+                }
+                resp = next;
+            }
         }
 
         ListLive2D => {
-            print(&client.send(&Live2DParameterListRequest {}).await?)?;
+            print(&send(client, &Live2DParameterListRequest {}).await?)?;
+        }
+
+        ListInputs(filter) => {
+            let mut resp = send(client, &InputParameterListRequest {}).await?;
+            filter.apply(&mut resp.default_parameters, |param| &param.name);
+            filter.apply(&mut resp.custom_parameters, |param| &param.name);
+            print(&resp)?;
+        }
+
+        Record {
+            out,
+            rate,
+            params,
+            duration,
+        } => {
+            print(&animation::record(client, &out, rate, &params, duration).await?)?;
+        }
+
+        Replay {
+            file,
+            looping,
+            speed,
+            ramp,
+            face_found,
+        } => {
+            let summary =
+                animation::replay(client, &file, looping, speed, ramp, face_found).await?;
+            print(&summary)?;
         }
 
-        ListInputs => {
-            print(&client.send(&InputParameterListRequest {}).await?)?;
+        Monitor {
+            interval,
+            threshold,
+            params,
+        } => {
+            // Last printed value of each parameter, starting from the first snapshot
+            let mut values: std::collections::HashMap<String, f64> =
+                std::collections::HashMap::new();
+            let mut first = true;
+
+            loop {
+                let resp = send(client, &InputParameterListRequest {}).await?;
+                let inputs = resp
+                    .default_parameters
+                    .into_iter()
+                    .chain(resp.custom_parameters)
+                    .filter(|param| params.is_empty() || params.contains(&param.name));
+
+                for param in inputs {
+                    match values.get(&param.name).copied() {
+                        Some(previous) if (param.value - previous).abs() > threshold => {
+                            print(&json!({
+                                "name": param.name,
+                                "value": param.value,
+                                "previous": previous,
+                                "delta": param.value - previous,
+                            }))?;
+                            values.insert(param.name, param.value);
+                        }
+                        None if first => {
+                            values.insert(param.name, param.value);
+                        }
+                        None => {
+                            // A parameter added after the first snapshot (e.g., by a plugin)
+                            print(&json!({ "name": param.name, "value": param.value }))?;
+                            values.insert(param.name, param.value);
+                        }
+                        Some(_) => {}
+                    }
+                }
+
+                first = false;
+                tokio::time::sleep(interval).await;
+            }
         }
 
         Delete { name } => {
-            let resp = client
-                .send(&ParameterDeletionRequest {
+            let resp = send(
+                client,
+                &ParameterDeletionRequest {
                     parameter_name: name,
-                })
-                .await?;
+                },
+            )
+            .await?;
 
             print(&resp)?;
         }
 
+        Inject(req) if req.stdin => stdin_injection(client, &req).await?,
+
         Inject(req) => {
-            let mode = if req.add {
-                InjectParameterDataMode::Add
-            } else {
-                InjectParameterDataMode::Set
+            if req.id.is_some() && req.value.is_none() && req.from.is_none() {
+                bail!("a value (or `--from` and `--to`) must be given for the parameter");
+            }
+
+            let (resp, value) = match (req.from, req.to, req.over) {
+                (Some(from), Some(to), Some(over)) => {
+                    let resp = ramp_injection(client, &req, from, to, over).await?;
+                    (resp, Some(to))
+                }
+                _ => (
+                    send(client, &inject_request(&req, req.value)).await?,
+                    req.value,
+                ),
             };
+            print(&resp)?;
 
-            let resp = client
-                .send(&InjectParameterDataRequest {
-                    face_found: req.face_found,
-                    mode: Some(mode.into()),
-                    parameter_values: vec![ParameterValue {
-                        id: req.id,
-                        value: req.value,
-                        weight: req.weight,
-                    }],
-                })
-                .await?;
+            if req.forever || req.hold.is_some() {
+                let deadline = req.hold.map(|hold| tokio::time::Instant::now() + hold);
+                let inject_req = inject_request(&req, value);
+                repeat_injection(client, &inject_req, deadline, req.interval).await?;
+            }
+        }
 
-            print(&resp)?;
+        Oscillate(req) => oscillate(client, &req).await?,
+
+        Play {
+            file,
+            easing,
+            fps,
+            face_found,
+        } => {
+            let animation = animation::Animation::load(&file)?;
+            if let Some(resp) = animation::play(client, &animation, easing, fps, face_found).await?
+            {
+                print(&resp)?;
+            }
         }
     }
 
@@ -254,26 +1394,100 @@ async fn handle_hotkeys_command(client: &mut Client, command: HotkeysCommand) ->
         List {
             model_id,
             live2d_file,
+            hotkey_type,
+            filter,
+            ids_only,
         } => {
-            let resp = client
-                .send(&HotkeysInCurrentModelRequest {
+            let mut resp = send(
+                client,
+                &HotkeysInCurrentModelRequest {
                     model_id,
                     live2d_item_file_name: live2d_file,
-                })
-                .await?;
-            print(&resp)?;
+                },
+            )
+            .await?;
+
+            if let Some(ty) = &hotkey_type {
+                resp.available_hotkeys
+                    .retain(|hotkey| hotkey.type_.as_str().eq_ignore_ascii_case(ty));
+            }
+            filter.apply(&mut resp.available_hotkeys, |hotkey| &hotkey.name);
+
+            if ids_only {
+                for hotkey in resp.available_hotkeys {
+                    print_line(&hotkey.hotkey_id)?;
+                }
+            } else {
+                print(&resp)?;
+            }
         }
 
         Trigger(req) => {
+            let item_instance_id = match (req.item, &req.live2d_file) {
+                (Some(item), _) => Some(item),
+                (None, Some(live2d_file)) => {
+                    let resp = send(
+                        client,
+                        &ItemListRequest {
+                            include_item_instances_in_scene: true,
+                            only_items_with_file_name: Some(live2d_file.clone()),
+                            ..ItemListRequest::default()
+                        },
+                    )
+                    .await?;
+
+                    let instance = resp
+                        .item_instances_in_scene
+                        .into_iter()
+                        .next()
+                        .with_context(|| {
+                            format!("no item found in scene with file name `{}`", live2d_file)
+                        })?;
+
+                    Some(instance.instance_id)
+                }
+                (None, None) => None,
+            };
+
             let hotkey_id = if let Some(id) = req.id {
                 id
             } else if let Some(name) = req.name {
-                let resp = client
-                    .send(&HotkeysInCurrentModelRequest {
+                let list = match &req.live2d_file {
+                    Some(live2d_file) => format!("hotkeys:{}", live2d_file),
+                    None => "hotkeys".to_owned(),
+                };
+
+                // The cached ID may be stale (e.g., a different model was loaded since), so look
+                // it up again if triggering it fails
+                if let Some(hotkey_id) = cache::get(&list, &name) {
+                    let req = HotkeyTriggerRequest {
+                        hotkey_id,
+                        item_instance_id: item_instance_id.clone(),
+                    };
+                    match send(client, &req).await {
+                        Ok(resp) => return print(&resp),
+                        Err(e) if e.is::<DryRun>() => return Err(e),
+                        Err(e) => {
+                            debug!(error = %format!("{:#}", e), "Cached hotkey ID failed");
+                            cache::invalidate(&list);
+                        }
+                    }
+                }
+
+                let resp = send(
+                    client,
+                    &HotkeysInCurrentModelRequest {
                         model_id: None,
-                        live2d_item_file_name: None,
-                    })
-                    .await?;
+                        live2d_item_file_name: req.live2d_file,
+                    },
+                )
+                .await?;
+                cache::put(
+                    &list,
+                    resp.available_hotkeys
+                        .iter()
+                        .map(|hotkey| (&hotkey.name, &hotkey.hotkey_id)),
+                );
 
                 resp.available_hotkeys
                     .into_iter()
@@ -284,12 +1498,14 @@ async fn handle_hotkeys_command(client: &mut Client, command: HotkeysCommand) ->
                 bail!("either `id` or `name` must be specified");
             };
 
-            let resp = client
-                .send(&HotkeyTriggerRequest {
+            let resp = send(
+                client,
+                &HotkeyTriggerRequest {
                     hotkey_id,
-                    item_instance_id: req.item,
-                })
-                .await?;
+                    item_instance_id,
+                },
+            )
+            .await?;
             print(&resp)?;
         }
     }
@@ -301,13 +1517,16 @@ async fn handle_artmeshes_command(client: &mut Client, command: ArtmeshesCommand
     use ArtmeshesCommand::*;
 
     match command {
-        List => {
-            print(&client.send(&ArtMeshListRequest {}).await?)?;
+        List(filter) => {
+            let mut resp = send(client, &ArtMeshListRequest {}).await?;
+            filter.apply(&mut resp.art_mesh_names, String::as_str);
+            print(&resp)?;
         }
 
         Tint(req) => {
-            let resp = client
-                .send(&ColorTintRequest {
+            let resp = send(
+                client,
+                &ColorTintRequest {
                     color_tint: ColorTint {
                         color_r: req.color.r,
                         color_g: req.color.g,
@@ -324,8 +1543,9 @@ async fn handle_artmeshes_command(client: &mut Client, command: ArtmeshesCommand
                         tag_exact: req.tag_exact,
                         tag_contains: req.tag_contains,
                     },
-                })
-                .await?;
+                },
+            )
+            .await?;
 
             print(&resp)?;
 
@@ -335,7 +1555,7 @@ async fn handle_artmeshes_command(client: &mut Client, command: ArtmeshesCommand
                     "Tint request successful. Adding delay before exiting..."
                 );
 
-                tokio::time::sleep(req.duration).await;
+                hold_connection(client, req.duration).await?;
             }
         }
 
@@ -344,15 +1564,27 @@ async fn handle_artmeshes_command(client: &mut Client, command: ArtmeshesCommand
             set_help,
             count,
             preselect,
+            timeout,
         } => {
-            let resp = client
-                .send(&ArtMeshSelectionRequest {
-                    text_override: set_text,
-                    help_override: set_help,
-                    requested_art_mesh_count: count.unwrap_or(0),
-                    active_art_meshes: preselect,
-                })
-                .await?;
+            let req = ArtMeshSelectionRequest {
+                text_override: set_text,
+                help_override: set_help,
+                requested_art_mesh_count: count.unwrap_or(0),
+                active_art_meshes: preselect,
+            };
+
+            // The selection window is closed when we disconnect, so on timeout we can just exit
+            let resp = match timeout {
+                Some(timeout) => tokio::time::timeout(timeout, send(client, &req))
+                    .await
+                    .with_context(|| {
+                        format!(
+                            "timed out after {:?} waiting for art mesh selection",
+                            timeout
+                        )
+                    })??,
+                None => send(client, &req).await?,
+            };
 
             print(&resp)?;
         }
@@ -361,23 +1593,253 @@ async fn handle_artmeshes_command(client: &mut Client, command: ArtmeshesCommand
     Ok(())
 }
 
+/// Builds an injection request with the given value for the positional parameter ID (if any),
+/// along with any `--param` values.
+fn inject_request(req: &InjectParam, value: Option<f64>) -> InjectParameterDataRequest {
+    let mode = if req.add {
+        InjectParameterDataMode::Add
+    } else {
+        InjectParameterDataMode::Set
+    };
+
+    let mut parameter_values = Vec::with_capacity(req.params.len() + 1);
+    if let (Some(id), Some(value)) = (&req.id, value) {
+        parameter_values.push(ParameterValue {
+            id: id.clone(),
+            value,
+            weight: req.weight,
+        });
+    }
+    parameter_values.extend(req.params.iter().map(|param| ParameterValue {
+        id: param.id.clone(),
+        value: param.value,
+        weight: param.weight,
+    }));
+
+    InjectParameterDataRequest {
+        face_found: req.face_found,
+        mode: Some(mode.into()),
+        parameter_values,
+    }
+}
+
+/// Injects values going from `from` to `to` over a duration (using the `--easing` function), at
+/// `--fps` values per second. Returns the response to the last value.
+async fn ramp_injection(
+    client: &mut Client,
+    req: &InjectParam,
+    from: f64,
+    to: f64,
+    over: Duration,
+) -> Result<InjectParameterDataResponse> {
+    if req.fps.is_nan() || req.fps <= 0.0 {
+        bail!("`--fps` should be greater than 0");
+    }
+
+    let frames = ((over.as_secs_f64() * req.fps).ceil() as u64).max(1);
+    let mut ticker = tokio::time::interval(Duration::from_secs_f64(1.0 / req.fps));
+    ticker.tick().await;
+
+    let mut resp = send(client, &inject_request(req, Some(from))).await?;
+    for frame in 1..=frames {
+        ticker.tick().await;
+        let t = req.easing.apply(frame as f64 / frames as f64);
+        resp = send(client, &inject_request(req, Some(from + (to - from) * t))).await?;
+    }
+
+    Ok(resp)
+}
+
+/// Injects a waveform into a parameter at `--fps` values per second, until the `--duration`
+/// elapses (or forever, if there is none). Stops early on Ctrl-C. Prints the response to the
+/// first value.
+async fn oscillate(client: &mut Client, req: &OscillateParam) -> Result<()> {
+    if req.fps.is_nan() || req.fps <= 0.0 {
+        bail!("`--fps` should be greater than 0");
+    }
+    if req.period.is_zero() {
+        bail!("`--period` should be greater than 0");
+    }
+
+    let started = tokio::time::Instant::now();
+    let deadline = req.duration.map(|duration| started + duration);
+    let mut ticker = tokio::time::interval(Duration::from_secs_f64(1.0 / req.fps));
+
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+
+    let mut printed = false;
+    loop {
+        tokio::select! {
+            _ = events::sleep_until(deadline) => return Ok(()),
+            _ = &mut ctrl_c => return Ok(()),
+            _ = ticker.tick() => {
+                let phase = (started.elapsed().as_secs_f64() / req.period.as_secs_f64()).fract();
+                let value = req.min + (req.max - req.min) * req.wave.apply(phase);
+
+                let resp = send(
+                    client,
+                    &InjectParameterDataRequest {
+                        face_found: req.face_found,
+                        mode: Some(InjectParameterDataMode::Set.into()),
+                        parameter_values: vec![ParameterValue {
+                            id: req.id.clone(),
+                            value,
+                            weight: req.weight,
+                        }],
+                    },
+                )
+                .await?;
+
+                if !printed {
+                    print(&resp)?;
+                    printed = true;
+                }
+            }
+        }
+    }
+}
+
+/// Sends an injection request for each line of JSON read from stdin, re-sending the last one at
+/// `--interval` while waiting for the next line. Invalid lines are logged and skipped. Stops at
+/// the end of input or on Ctrl-C, and prints the response to the first request.
+async fn stdin_injection(client: &mut Client, req: &InjectParam) -> Result<()> {
+    use tokio::io::AsyncBufReadExt;
+
+    let mut lines = tokio::io::BufReader::new(tokio::io::stdin()).lines();
+    let mut ticker = tokio::time::interval(req.interval);
+    let mut last: Option<InjectParameterDataRequest> = None;
+    let mut line_number = 0;
+
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+
+    loop {
+        tokio::select! {
+            _ = &mut ctrl_c => return Ok(()),
+            line = lines.next_line() => {
+                let line = match line.context("failed to read from stdin")? {
+                    Some(line) => line,
+                    None => return Ok(()),
+                };
+                line_number += 1;
+
+                let line = line.trim();
+                if line.is_empty() {
+                    continue;
+                }
+
+                let parameter_values = if line.starts_with('[') {
+                    serde_json::from_str(line)
+                } else {
+                    serde_json::from_str(line).map(|value| vec![value])
+                };
+                let parameter_values: Vec<ParameterValue> = match parameter_values {
+                    Ok(values) => values,
+                    Err(e) => {
+                        warn!(error = %e, line = line_number, "Skipping invalid input line");
+                        continue;
+                    }
+                };
+
+                let inject_req = InjectParameterDataRequest {
+                    parameter_values,
+                    ..inject_request(req, None)
+                };
+                let resp = send(client, &inject_req).await?;
+                if last.is_none() {
+                    print(&resp)?;
+                }
+                last = Some(inject_req);
+                ticker.reset();
+            }
+            _ = ticker.tick(), if last.is_some() => {
+                if let Some(inject_req) = &last {
+                    send(client, inject_req).await?;
+                }
+            }
+        }
+    }
+}
+
+/// Re-sends an injection request at an interval until the deadline (or forever, if there is
+/// none), since VTube Studio resets injected values that aren't updated every second. Stops early
+/// on Ctrl-C.
+async fn repeat_injection(
+    client: &mut Client,
+    req: &InjectParameterDataRequest,
+    deadline: Option<tokio::time::Instant>,
+    interval: Duration,
+) -> Result<()> {
+    let mut ticker = tokio::time::interval(interval);
+    // The first tick completes immediately, but the request was just sent
+    ticker.tick().await;
+
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+
+    loop {
+        tokio::select! {
+            _ = events::sleep_until(deadline) => return Ok(()),
+            _ = &mut ctrl_c => return Ok(()),
+            _ = ticker.tick() => {
+                send(client, req).await?;
+            }
+        }
+    }
+}
+
 async fn handle_models_command(client: &mut Client, command: ModelsCommand) -> Result<()> {
     use ModelsCommand::*;
 
     match command {
-        List => {
-            print(&client.send(&AvailableModelsRequest {}).await?)?;
+        List(filter) => {
+            let mut resp = send(client, &AvailableModelsRequest {}).await?;
+            filter.apply(&mut resp.available_models, |model| &model.model_name);
+            print(&resp)?;
         }
 
-        Current => {
-            print(&client.send(&CurrentModelRequest {}).await?)?;
+        Current { watch, interval } => {
+            let mut resp = send(client, &CurrentModelRequest {}).await?;
+            print(&resp)?;
+
+            if watch {
+                loop {
+                    tokio::time::sleep(interval).await;
+
+                    let next = send(client, &CurrentModelRequest {}).await?;
+                    if next.model_loaded != resp.model_loaded || next.model_id != resp.model_id {
+                        print(&next)?;
+                    }
+                    resp = next;
+                }
+            }
         }
 
         Load { id, name } => {
             let model_id = if let Some(id) = id {
                 id
             } else if let Some(name) = name {
-                let resp = client.send(&AvailableModelsRequest {}).await?;
+                // The cached ID may be stale (e.g., the model was deleted), so look it up again
+                // if loading it fails
+                if let Some(model_id) = cache::get("models", &name) {
+                    match send(client, &ModelLoadRequest { model_id }).await {
+                        Ok(resp) => return print(&resp),
+                        Err(e) if e.is::<DryRun>() => return Err(e),
+                        Err(e) => {
+                            debug!(error = %format!("{:#}", e), "Cached model ID failed");
+                            cache::invalidate("models");
+                        }
+                    }
+                }
+
+                let resp = send(client, &AvailableModelsRequest {}).await?;
+                cache::put(
+                    "models",
+                    resp.available_models
+                        .iter()
+                        .map(|model| (&model.model_name, &model.model_id)),
+                );
 
                 resp.available_models
                     .into_iter()
@@ -388,21 +1850,23 @@ async fn handle_models_command(client: &mut Client, command: ModelsCommand) -> R
                 bail!("either `id` or `name` must be specified");
             };
 
-            let resp = client.send(&ModelLoadRequest { model_id }).await?;
+            let resp = send(client, &ModelLoadRequest { model_id }).await?;
             print(&resp)?;
         }
 
         Move(req) => {
-            let resp = client
-                .send(&MoveModelRequest {
+            let resp = send(
+                client,
+                &MoveModelRequest {
                     time_in_seconds: req.duration.as_millis() as f64 / 1000.0,
                     values_are_relative_to_model: req.relative,
                     position_x: req.x,
                     position_y: req.y,
                     rotation: req.rotation,
                     size: req.size,
-                })
-                .await?;
+                },
+            )
+            .await?;
             print(&resp)?;
         }
     }
@@ -418,32 +1882,40 @@ async fn handle_expressions_command(
 
     match command {
         List { details, file } => {
-            let resp = client
-                .send(&ExpressionStateRequest {
+            let resp = send(
+                client,
+                &ExpressionStateRequest {
                     details,
                     expression_file: file,
-                })
-                .await?;
+                },
+            )
+            .await?;
             print(&resp)?;
         }
 
-        Activate { file } => {
-            let resp = client
-                .send(&ExpressionActivationRequest {
+        Activate { file, fade } => {
+            let resp = send(
+                client,
+                &FadeExpressionRequest {
                     expression_file: file,
+                    fade_time: fade.map(|fade| fade.as_secs_f64()),
                     active: true,
-                })
-                .await?;
+                },
+            )
+            .await?;
             print(&resp)?;
         }
 
-        Deactivate { file } => {
-            let resp = client
-                .send(&ExpressionActivationRequest {
+        Deactivate { file, fade } => {
+            let resp = send(
+                client,
+                &FadeExpressionRequest {
                     expression_file: file,
+                    fade_time: fade.map(|fade| fade.as_secs_f64()),
                     active: false,
-                })
-                .await?;
+                },
+            )
+            .await?;
             print(&resp)?;
         }
     }
@@ -451,76 +1923,159 @@ async fn handle_expressions_command(
     Ok(())
 }
 
+/// `ExpressionActivationRequest` with the `fadeTime` field, which vtubestudio's request type
+/// doesn't have.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FadeExpressionRequest {
+    expression_file: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fade_time: Option<f64>,
+    active: bool,
+}
+
+impl Request for FadeExpressionRequest {
+    const MESSAGE_TYPE: EnumString<RequestType> = ExpressionActivationRequest::MESSAGE_TYPE;
+    type Response = ExpressionActivationResponse;
+}
+
 async fn handle_ndi_command(client: &mut Client, command: NdiCommand) -> Result<()> {
     use NdiCommand::*;
 
     match command {
         GetConfig => {
-            let resp = client
-                .send(&NdiConfigRequest {
+            let resp = send(
+                client,
+                &NdiConfigRequest {
                     set_new_config: false,
                     ..NdiConfigRequest::default()
-                })
-                .await?;
+                },
+            )
+            .await?;
             print(&resp)?;
         }
 
         SetConfig(value) => {
-            let resp = client
-                .send(&NdiConfigRequest {
+            let resp = send(
+                client,
+                &NdiConfigRequest {
                     set_new_config: true,
                     ndi_active: value.active,
                     use_ndi5: value.use_ndi5,
                     use_custom_resolution: value.use_custom_resolution,
                     custom_width_ndi: value.width,
                     custom_height_ndi: value.height,
-                })
-                .await?;
+                },
+            )
+            .await?;
             print(&resp)?;
         }
+
+        On => set_ndi_active(client, |_| true).await?,
+        Off => set_ndi_active(client, |_| false).await?,
+        Toggle => set_ndi_active(client, |active| !active).await?,
     }
 
     Ok(())
 }
 
+/// Updates `ndi_active` based on its current value, preserving the rest of the NDI config.
+async fn set_ndi_active(client: &mut Client, f: impl FnOnce(bool) -> bool) -> Result<()> {
+    let current = send(
+        client,
+        &NdiConfigRequest {
+            set_new_config: false,
+            ..NdiConfigRequest::default()
+        },
+    )
+    .await?;
+
+    let resp = send(
+        client,
+        &NdiConfigRequest {
+            set_new_config: true,
+            ndi_active: Some(f(current.ndi_active)),
+            use_ndi5: Some(current.use_ndi5),
+            use_custom_resolution: Some(current.use_custom_resolution),
+            custom_width_ndi: Some(current.custom_width_ndi),
+            custom_height_ndi: Some(current.custom_height_ndi),
+        },
+    )
+    .await?;
+
+    print(&resp)
+}
+
 async fn handle_physics_command(client: &mut Client, command: PhysicsCommand) -> Result<()> {
     use PhysicsCommand::*;
 
     match command {
         Get => {
-            let resp = client.send(&GetCurrentModelPhysicsRequest {}).await?;
+            let resp = send(client, &GetCurrentModelPhysicsRequest {}).await?;
             print(&resp)?;
         }
 
-        Set(mut value) => {
+        Set(set) => {
             use SetPhysicsCommand::*;
 
             let mut req = SetCurrentModelPhysicsRequest::default();
-            let mut physics = PhysicsOverride::default();
 
-            match &mut value {
-                Base(base) => {
-                    physics.set_base_value = true;
-                    physics.value = base.value as f64;
-                    physics.override_seconds = base.duration.as_secs_f64();
-                }
-                Multiplier(mult) => {
-                    std::mem::swap(&mut physics.id, &mut mult.id);
-                    physics.value = mult.value;
-                    physics.override_seconds = mult.duration.as_secs_f64();
-                }
+            let base_override = |value: u8, duration: Duration| PhysicsOverride {
+                set_base_value: true,
+                value: value as f64,
+                override_seconds: duration.as_secs_f64(),
+                ..PhysicsOverride::default()
+            };
+
+            if let Some(value) = set.strength_base {
+                req.strength_overrides
+                    .push(base_override(value, set.duration));
             }
 
-            match value.kind() {
-                StrengthOrWind::Strength => {
-                    req.strength_overrides = vec![physics];
-                }
-                StrengthOrWind::Wind => {
-                    req.wind_overrides = vec![physics];
+            if let Some(value) = set.wind_base {
+                req.wind_overrides.push(base_override(value, set.duration));
+            }
+
+            if let Some(command) = set.command {
+                let (kind, overrides) = match command {
+                    Base(base) => (base.kind, vec![base_override(base.value, base.duration)]),
+                    Multiplier(mult) => {
+                        let values = match mult.value {
+                            Some(value) => vec![value; mult.ids.len()],
+                            None => mult.values,
+                        };
+
+                        if values.len() != mult.ids.len() {
+                            bail!("Expected one `--value` for each `--id`");
+                        }
+
+                        let overrides = mult
+                            .ids
+                            .into_iter()
+                            .zip(values)
+                            .map(|(id, value)| PhysicsOverride {
+                                id,
+                                value,
+                                set_base_value: false,
+                                override_seconds: mult.duration.as_secs_f64(),
+                            })
+                            .collect();
+
+                        (mult.kind, overrides)
+                    }
+                };
+
+                match kind {
+                    StrengthOrWind::Strength => req.strength_overrides.extend(overrides),
+                    StrengthOrWind::Wind => req.wind_overrides.extend(overrides),
                 }
             }
 
-            let resp = client.send(&req).await?;
+            if req.strength_overrides.is_empty() && req.wind_overrides.is_empty() {
+                bail!("Expected `--strength-base`, `--wind-base`, or a `base`/`multiplier` subcommand");
+            }
+
+            let resp = send(client, &req).await?;
             print(&resp)?;
         }
     }
@@ -528,6 +2083,37 @@ async fn handle_physics_command(client: &mut Client, command: PhysicsCommand) ->
     Ok(())
 }
 
+fn item_load_request(file_name: String, options: ItemLoadOptions) -> ItemLoadRequest {
+    ItemLoadRequest {
+        file_name,
+        position_x: options.x,
+        position_y: options.y,
+        size: options.size,
+        rotation: options.rotation,
+        fade_time: options.fade_time,
+        order: options.order,
+        fail_if_order_taken: options.fail_if_order_taken,
+        smoothing: options.smoothing,
+        censored: options.censored,
+        flipped: options.flipped,
+        locked: options.locked,
+        unload_when_plugin_disconnects: options.unload_on_disconnect,
+        custom_data_base64: None,
+        custom_data_ask_user_first: true,
+        custom_data_skip_asking_user_if_whitelisted: false,
+        custom_data_ask_timer: -1.0,
+    }
+}
+
+async fn hold_item(client: &mut Client, hold: Option<Duration>) -> Result<()> {
+    if let Some(hold) = hold {
+        info!(duration = ?hold, "Item loaded. Keeping connection open before exiting...");
+        hold_connection(client, hold).await?;
+    }
+
+    Ok(())
+}
+
 async fn handle_items_command(client: &mut Client, command: ItemsCommand) -> Result<()> {
     use ItemsCommand::*;
 
@@ -539,6 +2125,13 @@ async fn handle_items_command(client: &mut Client, command: ItemsCommand) -> Res
             with_file_name,
             with_instance_id,
         } => {
+            // Include everything if no specific lists were requested
+            let (spots, instances, files) = if spots || instances || files {
+                (spots, instances, files)
+            } else {
+                (true, true, true)
+            };
+
             let req = ItemListRequest {
                 include_available_spots: spots,
                 include_item_instances_in_scene: instances,
@@ -546,28 +2139,42 @@ async fn handle_items_command(client: &mut Client, command: ItemsCommand) -> Res
                 only_items_with_file_name: with_file_name,
                 only_items_with_instance_id: with_instance_id,
             };
-            let resp = client.send(&req).await?;
+            let resp = send(client, &req).await?;
             print(&resp)?;
         }
         Load(value) => {
+            let hold = value.options.hold;
+            let req = item_load_request(value.file_name, value.options);
+            let resp = send(client, &req).await?;
+            print(&resp)?;
+            hold_item(client, hold).await?;
+        }
+        LoadCustom(value) => {
+            let data = std::fs::read(&value.path)
+                .with_context(|| format!("failed to read image {}", value.path.display()))?;
+
+            let file_name = match value.file_name {
+                Some(name) => name,
+                None => value
+                    .path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .context("failed to get file name from path")?
+                    .to_owned(),
+            };
+
+            let hold = value.options.hold;
             let req = ItemLoadRequest {
-                file_name: value.file_name,
-                position_x: value.x,
-                position_y: value.y,
-                size: value.size,
-                rotation: value.rotation,
-                fade_time: value.fade_time,
-                order: value.order,
-                fail_if_order_taken: value.fail_if_order_taken,
-                smoothing: value.smoothing,
-                censored: value.censored,
-                flipped: value.flipped,
-                locked: value.locked,
-                unload_when_plugin_disconnects: false,
+                custom_data_base64: Some(base64::engine::general_purpose::STANDARD.encode(data)),
+                custom_data_ask_user_first: !value.no_ask_user_first,
+                custom_data_skip_asking_user_if_whitelisted: value.skip_asking_if_whitelisted,
+                custom_data_ask_timer: value.ask_timer,
+                ..item_load_request(file_name, value.options)
             };
 
-            let resp = client.send(&req).await?;
+            let resp = send(client, &req).await?;
             print(&resp)?;
+            hold_item(client, hold).await?;
         }
         Unload(value) => {
             let req = ItemUnloadRequest {
@@ -578,14 +2185,15 @@ async fn handle_items_command(client: &mut Client, command: ItemsCommand) -> Res
                 file_names: value.file,
             };
 
-            let resp = client.send(&req).await?;
+            let resp = send(client, &req).await?;
             print(&resp)?;
         }
         Move(value) => {
-            let item = ItemToMove {
-                item_instance_id: value.id,
+            // The other options (e.g., `--duration`) apply to every item
+            let item = |item_instance_id: String| ItemToMove {
+                item_instance_id,
                 time_in_seconds: value.duration.as_secs_f64(),
-                fade_mode: value.fade_mode,
+                fade_mode: value.fade_mode.clone(),
                 position_x: value.x,
                 position_y: value.y,
                 size: value.size,
@@ -595,11 +2203,24 @@ async fn handle_items_command(client: &mut Client, command: ItemsCommand) -> Res
                 flip: value.flip,
                 user_can_stop: value.user_can_stop,
             };
-            let req = ItemMoveRequest {
-                items_to_move: vec![item],
-            };
 
-            let resp = client.send(&req).await?;
+            let mut items_to_move = Vec::with_capacity(value.items.len() + 1);
+            if let Some(id) = value.id.clone() {
+                items_to_move.push(item(id));
+            }
+            for spec in value.items.iter().cloned() {
+                items_to_move.push(ItemToMove {
+                    position_x: spec.x,
+                    position_y: spec.y,
+                    rotation: spec.rotation,
+                    size: spec.size,
+                    ..item(spec.id)
+                });
+            }
+
+            let req = ItemMoveRequest { items_to_move };
+
+            let resp = send(client, &req).await?;
             print(&resp)?;
         }
         Animation(value) => {
@@ -622,7 +2243,48 @@ async fn handle_items_command(client: &mut Client, command: ItemsCommand) -> Res
                 animation_play_state,
             };
 
-            let resp = client.send(&req).await?;
+            let resp = send(client, &req).await?;
+            print(&resp)?;
+        }
+        Pin(value) => {
+            let vertex = |index: usize| value.vertex_id.get(index).copied().unwrap_or_default();
+            let weight = |index: usize| value.vertex_weight.get(index).copied().unwrap_or_default();
+            let model_id = match value.model_id {
+                Some(model_id) => model_id,
+                None => send(client, &CurrentModelRequest {}).await?.model_id,
+            };
+
+            let req = ItemPinRequest {
+                pin: true,
+                item_instance_id: value.item_instance_id,
+                angle_relative_to: value.angle_relative_to,
+                size_relative_to: value.size_relative_to,
+                vertex_pin_type: value.vertex_pin_type,
+                pin_info: ArtMeshPosition {
+                    model_id,
+                    art_mesh_id: value.art_mesh_id,
+                    angle: value.angle,
+                    size: value.size,
+                    vertex_id1: vertex(0),
+                    vertex_id2: vertex(1),
+                    vertex_id3: vertex(2),
+                    vertex_weight1: weight(0),
+                    vertex_weight2: weight(1),
+                    vertex_weight3: weight(2),
+                },
+            };
+
+            let resp = send(client, &req).await?;
+            print(&resp)?;
+        }
+        Unpin { item_instance_id } => {
+            let req = ItemPinRequest {
+                pin: false,
+                item_instance_id,
+                ..ItemPinRequest::default()
+            };
+
+            let resp = send(client, &req).await?;
             print(&resp)?;
         }
     }
@@ -630,40 +2292,221 @@ async fn handle_items_command(client: &mut Client, command: ItemsCommand) -> Res
     Ok(())
 }
 
-async fn handle_events_command(client: &mut Client, command: EventsCommand) -> Result<()> {
-    use EventsCommand::*;
+async fn handle_post_processing_command(
+    client: &mut Client,
+    command: PostProcessingCommand,
+) -> Result<()> {
+    use PostProcessingCommand::*;
 
-    let req = match command {
-        Test { message } => EventSubscriptionRequest::subscribe(&TestEventConfig {
-            test_message_for_event: message,
-        })?,
+    match command {
+        Set(value) => {
+            let req = PostProcessingUpdateRequest {
+                post_processing_on: !value.off,
+                set_post_processing_preset: value.preset.is_some(),
+                set_post_processing_values: !value.value.is_empty(),
+                preset_to_set: value.preset.unwrap_or_default(),
+                post_processing_fade_time: value.fade_time,
+                set_all_other_values_to_default: value.reset_others,
+                using_restricted_effects: value.restricted_effects,
+                randomize_all: value.randomize,
+                randomize_all_chaos_level: value.chaos_level,
+                post_processing_values: value
+                    .value
+                    .into_iter()
+                    .map(|value| PostProcessingValue {
+                        config_id: value.id,
+                        config_value: value.value.to_string(),
+                    })
+                    .collect(),
+            };
 
-        ModelLoaded { model_id } => {
-            EventSubscriptionRequest::subscribe(&ModelLoadedEventConfig { model_id })?
+            let resp = send(client, &req).await?;
+            print(&resp)?;
         }
+    }
 
-        TrackingStatusChanged {} => {
-            EventSubscriptionRequest::subscribe(&TrackingStatusChangedEventConfig {})?
-        }
+    Ok(())
+}
+
+async fn handle_permissions_command(
+    client: &mut Client,
+    command: PermissionsCommand,
+) -> Result<()> {
+    use PermissionsCommand::*;
 
-        BackgroundChanged {} => {
-            EventSubscriptionRequest::subscribe(&BackgroundChangedEventConfig {})?
+    match command {
+        Request { permission } => {
+            info!("Requesting permission. Please respond to the pop-up in the VTube Studio app.");
+
+            let resp = send(
+                client,
+                &PermissionRequest {
+                    requested_permission: Some(permission),
+                },
+            )
+            .await?;
+            print(&resp)?;
         }
+    }
 
-        ModelConfigChanged {} => {
-            EventSubscriptionRequest::subscribe(&ModelConfigChangedEventConfig {})?
+    Ok(())
+}
+
+/// Sends the subscription requests for the events command, returning the requests so that they
+/// can be resent when reconnecting.
+async fn handle_events_command(
+    client: &mut Client,
+    command: EventsCommand,
+) -> Result<Vec<EventSubscriptionRequest>> {
+    use EventsCommand::*;
+
+    let reqs = match command {
+        Test { message } => vec![EventSubscriptionRequest::subscribe(&TestEventConfig {
+            test_message_for_event: message,
+        })?],
+
+        ModelLoaded { model_id } => vec![EventSubscriptionRequest::subscribe(
+            &ModelLoadedEventConfig { model_id },
+        )?],
+
+        TrackingStatusChanged {} => vec![EventSubscriptionRequest::subscribe(
+            &TrackingStatusChangedEventConfig {},
+        )?],
+
+        BackgroundChanged {} => vec![EventSubscriptionRequest::subscribe(
+            &BackgroundChangedEventConfig {},
+        )?],
+
+        ModelConfigChanged {} => vec![EventSubscriptionRequest::subscribe(
+            &ModelConfigChangedEventConfig {},
+        )?],
+
+        ModelMoved {} => vec![EventSubscriptionRequest::subscribe(
+            &ModelMovedEventConfig {},
+        )?],
+
+        ModelOutline { draw } => vec![EventSubscriptionRequest::subscribe(
+            &ModelOutlineEventConfig { draw },
+        )?],
+
+        HotkeyTriggered {
+            only_for_action,
+            ignore_plugin_triggered,
+        } => vec![EventSubscriptionRequest::subscribe(
+            &HotkeyTriggeredEventConfig {
+                only_for_action: only_for_action.map(EnumString::new_from_str),
+                ignore_hotkeys_triggered_by_api: ignore_plugin_triggered,
+            },
+        )?],
+
+        ModelAnimation {
+            ignore_live2d_items,
+            ignore_idle_animations,
+        } => vec![EventSubscriptionRequest::subscribe(
+            &ModelAnimationEventConfig {
+                ignore_live2d_items,
+                ignore_idle_animations,
+            },
+        )?],
+
+        Item { item_name, item_id } => {
+            vec![EventSubscriptionRequest::subscribe(&ItemEventConfig {
+                item_instance_ids: item_id,
+                item_file_names: item_name,
+            })?]
         }
 
-        ModelMoved {} => EventSubscriptionRequest::subscribe(&ModelMovedEventConfig {})?,
+        ModelClicked {
+            only_clicks_on_model,
+        } => vec![EventSubscriptionRequest::subscribe(
+            &ModelClickedEventConfig {
+                only_clicks_on_model,
+            },
+        )?],
+
+        PostProcessing {} => vec![EventSubscriptionRequest::subscribe(
+            &PostProcessingEventConfig {},
+        )?],
+
+        Live2DCubismEditorConnected {} => vec![EventSubscriptionRequest::subscribe(
+            &Live2DCubismEditorConnectedEventConfig {},
+        )?],
+
+        All => EventType::ALL
+            .iter()
+            .filter(|event_type| !matches!(event_type, EventType::Test))
+            .map(|event_type| default_subscription(*event_type))
+            .collect::<Result<_>>()?,
+
+        Multi { event_types } => event_types
+            .into_iter()
+            .map(default_subscription)
+            .collect::<Result<_>>()?,
+
+        Replay { .. } => bail!("event replays don't require a connection"),
+
+        Unsubscribe { all, event_type } => {
+            let req = EventSubscriptionRequest {
+                event_name: if all {
+                    None
+                } else {
+                    event_type.map(|event_type| EnumString::new_from_str(event_type.name()))
+                },
+                subscribe: false,
+                ..EventSubscriptionRequest::default()
+            };
 
-        ModelOutline { draw } => {
-            EventSubscriptionRequest::subscribe(&ModelOutlineEventConfig { draw })?
+            print(&send(client, &req).await?)?;
+            return Ok(Vec::new());
         }
     };
 
-    let resp = client.send(&req).await?;
-    let resp_json = serde_json::to_string(&resp)?;
-    eprintln!("{resp_json}");
+    events::subscribe(client, &reqs).await?;
+    Ok(reqs)
+}
+
+/// Subscription request for an event type, using the default event config.
+fn default_subscription(event_type: EventType) -> Result<EventSubscriptionRequest> {
+    use EventType::*;
 
-    Ok(())
+    let req = match event_type {
+        Test => EventSubscriptionRequest::subscribe(&TestEventConfig {
+            test_message_for_event: "Test".to_owned(),
+        })?,
+        ModelLoaded => {
+            EventSubscriptionRequest::subscribe(&ModelLoadedEventConfig { model_id: vec![] })?
+        }
+        TrackingStatusChanged => {
+            EventSubscriptionRequest::subscribe(&TrackingStatusChangedEventConfig {})?
+        }
+        BackgroundChanged => EventSubscriptionRequest::subscribe(&BackgroundChangedEventConfig {})?,
+        ModelConfigChanged => {
+            EventSubscriptionRequest::subscribe(&ModelConfigChangedEventConfig {})?
+        }
+        ModelMoved => EventSubscriptionRequest::subscribe(&ModelMovedEventConfig {})?,
+        ModelOutline => {
+            EventSubscriptionRequest::subscribe(&ModelOutlineEventConfig { draw: false })?
+        }
+        HotkeyTriggered => EventSubscriptionRequest::subscribe(&HotkeyTriggeredEventConfig {
+            only_for_action: None,
+            ignore_hotkeys_triggered_by_api: false,
+        })?,
+        ModelAnimation => EventSubscriptionRequest::subscribe(&ModelAnimationEventConfig {
+            ignore_live2d_items: false,
+            ignore_idle_animations: false,
+        })?,
+        Item => EventSubscriptionRequest::subscribe(&ItemEventConfig {
+            item_instance_ids: vec![],
+            item_file_names: vec![],
+        })?,
+        ModelClicked => EventSubscriptionRequest::subscribe(&ModelClickedEventConfig {
+            only_clicks_on_model: false,
+        })?,
+        PostProcessing => EventSubscriptionRequest::subscribe(&PostProcessingEventConfig {})?,
+        Live2DCubismEditorConnected => {
+            EventSubscriptionRequest::subscribe(&Live2DCubismEditorConnectedEventConfig {})?
+        }
+    };
+
+    Ok(req)
 }
@@ -0,0 +1,453 @@
+use anyhow::{Context, Result};
+use handlebars::Handlebars;
+use serde_json::Value;
+use std::io::IsTerminal;
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OutputFormat {
+    Json,
+    Csv,
+    Tsv,
+    Table,
+    Logfmt,
+}
+
+impl OutputFormat {
+    pub fn variants() -> &'static [&'static str] {
+        &["json", "csv", "tsv", "table", "logfmt"]
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Ok(match value {
+            "json" => Self::Json,
+            "csv" => Self::Csv,
+            "tsv" => Self::Tsv,
+            "table" => Self::Table,
+            "logfmt" => Self::Logfmt,
+            other => anyhow::bail!(
+                "Unknown value `{}`. Should be one of `json`, `csv`, `tsv`, `table`, or `logfmt`.",
+                other
+            ),
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ErrorFormat {
+    Text,
+    Json,
+}
+
+impl ErrorFormat {
+    pub fn variants() -> &'static [&'static str] {
+        &["text", "json"]
+    }
+}
+
+impl FromStr for ErrorFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Ok(match value {
+            "text" => Self::Text,
+            "json" => Self::Json,
+            other => anyhow::bail!(
+                "Unknown value `{}`. Should be either `text` or `json`.",
+                other
+            ),
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ColorMode {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorMode {
+    pub fn variants() -> &'static [&'static str] {
+        &["auto", "always", "never"]
+    }
+
+    /// Whether output should be colorized. In `auto` mode, this is only the case if stdout is a
+    /// terminal and `NO_COLOR` isn't set.
+    pub fn enabled(&self) -> bool {
+        match self {
+            Self::Always => true,
+            Self::Never => false,
+            Self::Auto => std::env::var_os("NO_COLOR").is_none() && std::io::stdout().is_terminal(),
+        }
+    }
+}
+
+impl FromStr for ColorMode {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Ok(match value {
+            "auto" => Self::Auto,
+            "always" => Self::Always,
+            "never" => Self::Never,
+            other => anyhow::bail!(
+                "Unknown value `{}`. Should be one of `auto`, `always`, or `never`.",
+                other
+            ),
+        })
+    }
+}
+
+const KEY_COLOR: &str = "1;34";
+const STRING_COLOR: &str = "32";
+const NUMBER_COLOR: &str = "36";
+const BOOL_COLOR: &str = "33";
+const NULL_COLOR: &str = "90";
+
+/// Serializes a value as JSON with ANSI colors for keys and values, matching the layout of
+/// `serde_json::to_string` (or `to_string_pretty` if `pretty` is set).
+pub fn to_colored_json(value: &Value, pretty: bool) -> String {
+    let mut out = String::new();
+    write_colored(&mut out, value, pretty, 0);
+    out
+}
+
+fn write_colored(out: &mut String, value: &Value, pretty: bool, depth: usize) {
+    match value {
+        Value::Null => paint(out, NULL_COLOR, "null"),
+        Value::Bool(b) => paint(out, BOOL_COLOR, &b.to_string()),
+        Value::Number(n) => paint(out, NUMBER_COLOR, &n.to_string()),
+        Value::String(_) => paint(out, STRING_COLOR, &value.to_string()),
+        Value::Array(items) => {
+            let entries = items.iter().map(|item| (None, item));
+            write_container(out, ('[', ']'), entries, pretty, depth);
+        }
+        Value::Object(object) => {
+            let entries = object.iter().map(|(key, field)| (Some(key), field));
+            write_container(out, ('{', '}'), entries, pretty, depth);
+        }
+    }
+}
+
+fn write_container<'a>(
+    out: &mut String,
+    (open, close): (char, char),
+    entries: impl Iterator<Item = (Option<&'a String>, &'a Value)>,
+    pretty: bool,
+    depth: usize,
+) {
+    let mut entries = entries.peekable();
+
+    out.push(open);
+    if entries.peek().is_none() {
+        out.push(close);
+        return;
+    }
+
+    for (i, (key, value)) in entries.enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        if pretty {
+            out.push('\n');
+            out.push_str(&"  ".repeat(depth + 1));
+        }
+        if let Some(key) = key {
+            paint(out, KEY_COLOR, &Value::String(key.clone()).to_string());
+            out.push_str(if pretty { ": " } else { ":" });
+        }
+        write_colored(out, value, pretty, depth + 1);
+    }
+
+    if pretty {
+        out.push('\n');
+        out.push_str(&"  ".repeat(depth));
+    }
+    out.push(close);
+}
+
+fn paint(out: &mut String, color: &str, text: &str) {
+    out.push_str(&format!("\x1b[{}m{}\x1b[0m", color, text));
+}
+
+/// Gets the template from `--template-file` or `--template`, if either was given.
+pub fn read_template(
+    template: Option<&str>,
+    template_file: Option<&Path>,
+) -> Result<Option<String>> {
+    match template_file {
+        Some(path) => std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read template from {:?}", path))
+            .map(Some),
+        None => Ok(template.map(str::to_owned)),
+    }
+}
+
+/// Renders a value with a Handlebars template. Output isn't HTML-escaped.
+pub fn render_template(template: &str, value: &Value) -> Result<String> {
+    let mut handlebars = Handlebars::new();
+    handlebars.register_escape_fn(handlebars::no_escape);
+
+    handlebars
+        .render_template(template, value)
+        .context("failed to render template")
+}
+
+/// Renders a value as delimiter-separated rows with a header line.
+///
+/// Arrays are rendered as one row per element. For objects, the first array field is used as the
+/// rows (e.g., `availableHotkeys` in the `hotkeys list` response), and otherwise the object itself
+/// is rendered as a single row. Nested objects are flattened into dotted column names.
+pub fn to_delimited(value: &Value, format: OutputFormat) -> String {
+    let rows = table_rows(value);
+    let columns = columns(&rows);
+
+    let mut lines = Vec::with_capacity(rows.len() + 1);
+    lines.push(join_fields(columns.iter().map(String::as_str), format));
+    for row in &rows {
+        lines.push(join_fields(cells(row, &columns), format));
+    }
+
+    lines.join("\n")
+}
+
+/// Renders a value as rows (chosen the same way as `to_delimited`) in aligned columns, for
+/// reading in a terminal.
+pub fn to_table(value: &Value) -> String {
+    let rows = table_rows(value);
+    let columns = columns(&rows);
+
+    let mut widths: Vec<usize> = columns
+        .iter()
+        .map(|column| column.chars().count())
+        .collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(cells(row, &columns)) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let format_line = |fields: Vec<&str>| {
+        let padded = fields
+            .iter()
+            .zip(&widths)
+            .map(|(field, width)| format!("{:width$}", field, width = width))
+            .collect::<Vec<_>>();
+        padded.join("  ").trim_end().to_owned()
+    };
+
+    let mut lines = Vec::with_capacity(rows.len() + 2);
+    lines.push(format_line(columns.iter().map(String::as_str).collect()));
+    let separators: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+    lines.push(format_line(separators.iter().map(String::as_str).collect()));
+    for row in &rows {
+        lines.push(format_line(cells(row, &columns).collect()));
+    }
+
+    lines.join("\n")
+}
+
+/// Renders a value as logfmt (`key=value` pairs), e.g., for events. Arrays are rendered as one
+/// line per element, and nested objects are flattened into dotted keys.
+pub fn to_logfmt(value: &Value) -> String {
+    let elements: Vec<&Value> = match value {
+        Value::Array(elements) => elements.iter().collect(),
+        _ => vec![value],
+    };
+
+    let lines = elements.into_iter().map(|element| {
+        let mut row = Row::new();
+        flatten("", element, &mut row);
+        row.iter()
+            .map(|(key, text)| format!("{}={}", key, escape_logfmt(text)))
+            .collect::<Vec<_>>()
+            .join(" ")
+    });
+
+    lines.collect::<Vec<_>>().join("\n")
+}
+
+/// Column names across all rows, in the order they first appear.
+fn columns(rows: &[Row]) -> Vec<String> {
+    let mut columns: Vec<String> = Vec::new();
+    for row in rows {
+        for (column, _) in row {
+            if !columns.contains(column) {
+                columns.push(column.clone());
+            }
+        }
+    }
+    columns
+}
+
+/// Cell text for each column in the row, empty if the row doesn't have that column.
+fn cells<'a>(row: &'a Row, columns: &'a [String]) -> impl Iterator<Item = &'a str> {
+    columns.iter().map(move |column| {
+        row.iter()
+            .find(|(key, _)| key == column)
+            .map_or("", |(_, text)| text.as_str())
+    })
+}
+
+/// A row of (column name, cell text) pairs, in the order the fields appeared.
+type Row = Vec<(String, String)>;
+
+/// Flattens a value into rows.
+fn table_rows(value: &Value) -> Vec<Row> {
+    let elements: Vec<&Value> = match value {
+        Value::Array(elements) => elements.iter().collect(),
+        Value::Object(object) => match object.values().find(|field| field.is_array()) {
+            Some(Value::Array(elements)) => elements.iter().collect(),
+            _ => vec![value],
+        },
+        _ => vec![value],
+    };
+
+    elements
+        .into_iter()
+        .map(|element| {
+            let mut row = Row::new();
+            flatten("", element, &mut row);
+            row
+        })
+        .collect()
+}
+
+fn flatten(prefix: &str, value: &Value, row: &mut Row) {
+    match value {
+        Value::Object(object) => {
+            for (key, field) in object {
+                let column = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                flatten(&column, field, row);
+            }
+        }
+        _ => {
+            let column = if prefix.is_empty() { "value" } else { prefix };
+            row.push((column.to_owned(), cell_text(value)));
+        }
+    }
+}
+
+fn cell_text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn join_fields<'a>(fields: impl Iterator<Item = &'a str>, format: OutputFormat) -> String {
+    let (delimiter, escape): (&str, fn(&str) -> String) = match format {
+        OutputFormat::Tsv => ("\t", escape_tsv),
+        _ => (",", escape_csv),
+    };
+
+    fields.map(escape).collect::<Vec<_>>().join(delimiter)
+}
+
+fn escape_csv(field: &str) -> String {
+    if field.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+fn escape_logfmt(field: &str) -> String {
+    if field.is_empty() || field.contains(&[' ', '=', '"', '\\', '\n', '\r', '\t'][..]) {
+        Value::String(field.to_owned()).to_string()
+    } else {
+        field.to_owned()
+    }
+}
+
+fn escape_tsv(field: &str) -> String {
+    field.replace(&['\t', '\n', '\r'][..], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn delimited_rows_from_first_array_field() {
+        let value = json!({
+            "modelLoaded": true,
+            "availableHotkeys": [
+                { "name": "Wave", "keyCombination": [], "file": "a, b.exp3.json" },
+                { "name": "Smile", "position": { "x": 1 } },
+            ],
+        });
+
+        assert_eq!(
+            to_delimited(&value, OutputFormat::Csv),
+            "name,keyCombination,file,position.x\n\
+             Wave,[],\"a, b.exp3.json\",\n\
+             Smile,,,1"
+        );
+    }
+
+    #[test]
+    fn delimited_single_object() {
+        let value = json!({ "name": "say \"hi\"", "note": "a\tb", "value": null });
+
+        assert_eq!(
+            to_delimited(&value, OutputFormat::Csv),
+            "name,note,value\n\"say \"\"hi\"\"\",a\tb,"
+        );
+        assert_eq!(
+            to_delimited(&value, OutputFormat::Tsv),
+            "name\tnote\tvalue\nsay \"hi\"\ta b\t"
+        );
+    }
+
+    #[test]
+    fn delimited_scalars() {
+        assert_eq!(
+            to_delimited(&json!([1, "a"]), OutputFormat::Csv),
+            "value\n1\na"
+        );
+    }
+
+    #[test]
+    fn table_aligns_columns() {
+        let value = json!([
+            { "name": "Wave", "type": "ToggleExpression" },
+            { "name": "Smile", "type": "" },
+        ]);
+
+        assert_eq!(
+            to_table(&value),
+            "name   type\n\
+             -----  ----------------\n\
+             Wave   ToggleExpression\n\
+             Smile"
+        );
+    }
+
+    #[test]
+    fn logfmt_flattens_and_quotes() {
+        let value = json!({
+            "messageType": "ModelLoadedEvent",
+            "data": { "modelLoaded": true, "modelName": "My Model", "modelID": "" },
+        });
+
+        assert_eq!(
+            to_logfmt(&value),
+            "messageType=ModelLoadedEvent data.modelLoaded=true data.modelName=\"My Model\" \
+             data.modelID=\"\""
+        );
+        assert_eq!(to_logfmt(&json!([{ "a": 1 }, { "a": 2 }])), "a=1\na=2");
+    }
+}
@@ -0,0 +1,169 @@
+use anyhow::{bail, Context, Error, Result};
+use serde_json::Value;
+use std::str::FromStr;
+
+/// A jq-style path expression, e.g. `.availableModels[].modelName`.
+///
+/// Supports field access (`.name` or `."name with spaces"`), array indexing (`[0]`, or `[-1]` to
+/// count from the end), and iteration (`[]`). Missing fields and out-of-range indexes produce
+/// `null`, as in jq.
+#[derive(Debug, Clone)]
+pub struct Query {
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone)]
+enum Segment {
+    Field(String),
+    Index(i64),
+    Iterate,
+}
+
+impl Query {
+    /// Applies the query to a value. If the query iterates (`[]`), the results are collected into
+    /// an array.
+    pub fn apply(&self, value: Value) -> Value {
+        let mut values = vec![value];
+        let mut iterated = false;
+
+        for segment in &self.segments {
+            values = match segment {
+                Segment::Field(name) => values
+                    .into_iter()
+                    .map(|value| match value {
+                        Value::Object(mut object) => object.remove(name).unwrap_or(Value::Null),
+                        _ => Value::Null,
+                    })
+                    .collect(),
+                Segment::Index(index) => values
+                    .into_iter()
+                    .map(|value| match value {
+                        Value::Array(mut array) => {
+                            let len = array.len() as i64;
+                            let index = if *index < 0 { len + index } else { *index };
+                            if (0..len).contains(&index) {
+                                array.swap_remove(index as usize)
+                            } else {
+                                Value::Null
+                            }
+                        }
+                        _ => Value::Null,
+                    })
+                    .collect(),
+                Segment::Iterate => {
+                    iterated = true;
+                    values
+                        .into_iter()
+                        .flat_map(|value| match value {
+                            Value::Array(array) => array,
+                            Value::Object(object) => object.into_iter().map(|(_, v)| v).collect(),
+                            _ => Vec::new(),
+                        })
+                        .collect()
+                }
+            };
+        }
+
+        if iterated {
+            Value::Array(values)
+        } else {
+            values.pop().unwrap_or(Value::Null)
+        }
+    }
+}
+
+impl FromStr for Query {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let expr = value.trim();
+        if !expr.starts_with('.') {
+            bail!("query `{}` should start with `.`", value);
+        }
+
+        let mut segments = Vec::new();
+        let mut rest = expr;
+
+        while !rest.is_empty() {
+            if let Some(after_dot) = rest.strip_prefix('.') {
+                if let Some(quoted) = after_dot.strip_prefix('"') {
+                    let end = quoted
+                        .find('"')
+                        .with_context(|| format!("unterminated quote in query `{}`", value))?;
+                    segments.push(Segment::Field(quoted[..end].to_owned()));
+                    rest = &quoted[end + 1..];
+                } else {
+                    let end = after_dot
+                        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                        .unwrap_or(after_dot.len());
+                    if end > 0 {
+                        segments.push(Segment::Field(after_dot[..end].to_owned()));
+                    }
+                    rest = &after_dot[end..];
+                }
+            } else if let Some(after_bracket) = rest.strip_prefix('[') {
+                let end = after_bracket
+                    .find(']')
+                    .with_context(|| format!("unterminated `[` in query `{}`", value))?;
+                let inner = after_bracket[..end].trim();
+
+                segments.push(if inner.is_empty() {
+                    Segment::Iterate
+                } else {
+                    Segment::Index(inner.parse().with_context(|| {
+                        format!("invalid index `{}` in query `{}`", inner, value)
+                    })?)
+                });
+                rest = &after_bracket[end + 1..];
+            } else {
+                bail!("unexpected `{}` in query `{}`", rest, value);
+            }
+        }
+
+        Ok(Query { segments })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn query(expr: &str, value: Value) -> Value {
+        expr.parse::<Query>().unwrap().apply(value)
+    }
+
+    #[test]
+    fn fields() {
+        let value = json!({ "a": { "b": 1 }, "with space": 2 });
+        assert_eq!(query(".", value.clone()), value);
+        assert_eq!(query(".a.b", value.clone()), json!(1));
+        assert_eq!(query(".\"with space\"", value.clone()), json!(2));
+        assert_eq!(query(".missing.b", value), Value::Null);
+    }
+
+    #[test]
+    fn indexes() {
+        let value = json!({ "items": [1, 2, 3] });
+        assert_eq!(query(".items[0]", value.clone()), json!(1));
+        assert_eq!(query(".items[-1]", value.clone()), json!(3));
+        assert_eq!(query(".items[3]", value), Value::Null);
+    }
+
+    #[test]
+    fn iterate() {
+        let value = json!({ "availableModels": [{ "modelName": "A" }, { "modelName": "B" }] });
+        assert_eq!(
+            query(".availableModels[].modelName", value),
+            json!(["A", "B"])
+        );
+        assert_eq!(query(".[]", json!({ "a": 1, "b": 2 })), json!([1, 2]));
+    }
+
+    #[test]
+    fn invalid() {
+        for expr in ["a", ".\"a", ".a[0", ".a[x]", ".a b"] {
+            assert!(expr.parse::<Query>().is_err(), "{}", expr);
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use crate::exec;
+use crate::print_value;
+
+use anyhow::{bail, Context, Result};
+use futures_util::future::{join_all, FutureExt, LocalBoxFuture};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use vtubestudio::Client;
+
+/// A script for `vts run`, e.g.:
+///
+/// ```yaml
+/// vars:
+///   hotkey: Wave
+/// steps:
+///   - hotkeys trigger --name ${hotkey}
+///   - sleep: 500ms
+///   - repeat: 3
+///     steps:
+///       - params inject MyParam ${index}
+///       - sleep: 100ms
+///   - parallel:
+///       - items load first.png
+///       - items load second.png
+/// ```
+#[derive(Debug, Deserialize)]
+struct Script {
+    #[serde(default)]
+    vars: BTreeMap<String, Scalar>,
+    steps: Vec<Step>,
+}
+
+/// Variable value, which may be written as a string, number, or boolean.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Scalar {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Bool(bool),
+}
+
+impl std::fmt::Display for Scalar {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::String(s) => s.fmt(f),
+            Self::Integer(n) => n.fmt(f),
+            Self::Float(n) => n.fmt(f),
+            Self::Bool(b) => b.fmt(f),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Step {
+    /// A command line, without the `vts` prefix.
+    Command(String),
+    /// A command as a list of arguments, without the `vts` prefix.
+    Args(Vec<String>),
+    /// Wait for a duration (e.g., `500ms`).
+    Sleep { sleep: String },
+    /// Run the nested steps a number of times, with `${index}` set to the iteration (from 0).
+    Repeat { repeat: u64, steps: Vec<Step> },
+    /// Set variables for the following steps.
+    Set { set: BTreeMap<String, Scalar> },
+    /// Run the nested steps at the same time. Variables set by them aren't kept afterwards.
+    Parallel { parallel: Vec<Step> },
+}
+
+/// Runs the steps in a script file over a single connection, printing one line of output per
+/// command. Stops at the first failed step.
+pub async fn run_file(client: &mut Client, path: &Path) -> Result<()> {
+    let contents =
+        std::fs::read_to_string(path).with_context(|| format!("failed to read {:?}", path))?;
+    let script: Script = serde_yaml::from_str(&contents)
+        .with_context(|| format!("failed to parse script {:?}", path))?;
+
+    let mut vars = script
+        .vars
+        .into_iter()
+        .map(|(name, value)| (name, value.to_string()))
+        .collect();
+    run_steps(client, &script.steps, &mut vars).await
+}
+
+fn run_steps<'a>(
+    client: &'a mut Client,
+    steps: &'a [Step],
+    vars: &'a mut BTreeMap<String, String>,
+) -> LocalBoxFuture<'a, Result<()>> {
+    async move {
+        for step in steps {
+            match step {
+                Step::Command(line) => {
+                    let words = shell_words::split(&substitute(line, vars)?)
+                        .with_context(|| format!("invalid command `{}`", line))?;
+                    run_words(client, words).await?;
+                }
+                Step::Args(args) => {
+                    let words = args
+                        .iter()
+                        .map(|arg| substitute(arg, vars))
+                        .collect::<Result<_>>()?;
+                    run_words(client, words).await?;
+                }
+                Step::Sleep { sleep } => {
+                    let duration = parse_duration::parse(&substitute(sleep, vars)?)
+                        .with_context(|| format!("invalid sleep duration `{}`", sleep))?;
+                    tokio::time::sleep(duration).await;
+                }
+                Step::Repeat { repeat, steps } => {
+                    for index in 0..*repeat {
+                        vars.insert("index".to_owned(), index.to_string());
+                        run_steps(client, steps, vars).await?;
+                    }
+                }
+                Step::Set { set } => {
+                    for (name, value) in set {
+                        let value = substitute(&value.to_string(), vars)?;
+                        vars.insert(name.clone(), value);
+                    }
+                }
+                Step::Parallel { parallel } => {
+                    // Each step gets its own handle to the same connection, so that requests
+                    // are sent without waiting for the previous response
+                    let results = join_all(parallel.iter().map(|step| {
+                        let mut client = client.clone();
+                        let mut vars = vars.clone();
+                        async move {
+                            run_steps(&mut client, std::slice::from_ref(step), &mut vars).await
+                        }
+                    }))
+                    .await;
+                    for result in results {
+                        result?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+    .boxed_local()
+}
+
+async fn run_words(client: &mut Client, words: Vec<String>) -> Result<()> {
+    let description = words.join(" ");
+    let command = exec::parse_words(words)?;
+    let value = exec::run_captured(client, command)
+        .await
+        .with_context(|| format!("step `{}` failed", description))?;
+    print_value(&value)
+}
+
+/// Replaces `${name}` with the value of the variable.
+fn substitute(text: &str, vars: &BTreeMap<String, String>) -> Result<String> {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find("${") {
+        output.push_str(&rest[..start]);
+
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => bail!("unterminated `${{` in `{}`", text),
+        };
+        let name = &rest[start + 2..end];
+        let value = vars
+            .get(name)
+            .with_context(|| format!("unknown variable `{}` in `{}`", name, text))?;
+        output.push_str(value);
+
+        rest = &rest[end + 1..];
+    }
+
+    output.push_str(rest);
+    Ok(output)
+}
@@ -0,0 +1,382 @@
+use crate::args::Command;
+use crate::exec;
+use crate::print_value;
+
+use anyhow::Result;
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Editor, Helper};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{mpsc as std_mpsc, Mutex};
+use std::time::Duration;
+use structopt::clap::{ErrorKind, Shell};
+use structopt::StructOpt;
+use tokio::sync::mpsc;
+use tracing::warn;
+use vtubestudio::data::{AvailableModelsRequest, HotkeysInCurrentModelRequest};
+use vtubestudio::Client;
+
+/// How long tab completion waits for names to be fetched from VTube Studio.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Kinds of names that are fetched from VTube Studio for tab completion.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+enum NameKind {
+    Models,
+    Hotkeys,
+}
+
+/// Request from the completer (on the blocking readline thread) for names to be fetched.
+struct NameRequest {
+    kind: NameKind,
+    reply: std_mpsc::Sender<Vec<String>>,
+}
+
+/// Runs an interactive prompt, running each entered command over the same connection.
+pub async fn run(client: &mut Client) -> Result<()> {
+    let (requests_tx, mut requests_rx) = mpsc::unbounded_channel();
+
+    let mut editor = Editor::<ShellHelper>::new()?;
+    editor.set_helper(Some(ShellHelper {
+        commands: CommandTree::new(),
+        names: Mutex::new(HashMap::new()),
+        requests: requests_tx,
+    }));
+
+    let history_path = history_path();
+    if let Some(path) = &history_path {
+        let _ = editor.load_history(path);
+    }
+
+    loop {
+        // Readline blocks, so run it on another thread while serving name requests from the
+        // completer using the client here
+        let mut readline = tokio::task::spawn_blocking(move || {
+            let line = editor.readline("vts> ");
+            (editor, line)
+        });
+
+        let (returned_editor, line) = loop {
+            tokio::select! {
+                result = &mut readline => break result?,
+                Some(request) = requests_rx.recv() => {
+                    let names = fetch_names(client, request.kind).await.unwrap_or_else(|e| {
+                        warn!(error = %e, "Failed to fetch names for completion");
+                        Vec::new()
+                    });
+                    let _ = request.reply.send(names);
+                }
+            }
+        };
+        editor = returned_editor;
+
+        let line = match line {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        editor.add_history_entry(line);
+
+        if matches!(line, "exit" | "quit") {
+            break;
+        }
+
+        let result = match exec::parse_line(line) {
+            Ok(Some(command)) => exec::run_captured(client, command)
+                .await
+                .and_then(|value| print_value(&value)),
+            Ok(None) => Ok(()),
+            Err(e) => Err(e),
+        };
+
+        if let Err(e) = result {
+            match e.downcast_ref::<structopt::clap::Error>() {
+                Some(e)
+                    if matches!(
+                        e.kind,
+                        ErrorKind::HelpDisplayed | ErrorKind::VersionDisplayed
+                    ) =>
+                {
+                    println!("{}", e.message)
+                }
+                _ => eprintln!("Error: {:#}", e),
+            }
+        }
+
+        // The command may have changed the current model, so fetch names again when needed
+        if let Some(helper) = editor.helper() {
+            helper
+                .names
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .clear();
+        }
+    }
+
+    if let Some(path) = &history_path {
+        if let Some(dir) = path.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        if let Err(e) = editor.save_history(path) {
+            warn!(error = %e, ?path, "Failed to save shell history");
+        }
+    }
+
+    Ok(())
+}
+
+fn history_path() -> Option<PathBuf> {
+    directories::ProjectDirs::from("com.github", "walfie", "vtubestudio-cli")
+        .map(|dirs| dirs.data_dir().join("shell_history.txt"))
+}
+
+async fn fetch_names(client: &mut Client, kind: NameKind) -> Result<Vec<String>> {
+    Ok(match kind {
+        NameKind::Models => client
+            .send(&AvailableModelsRequest {})
+            .await?
+            .available_models
+            .into_iter()
+            .map(|model| model.model_name)
+            .collect(),
+        NameKind::Hotkeys => client
+            .send(&HotkeysInCurrentModelRequest {
+                model_id: None,
+                live2d_item_file_name: None,
+            })
+            .await?
+            .available_hotkeys
+            .into_iter()
+            .map(|hotkey| hotkey.name)
+            .collect(),
+    })
+}
+
+/// Subcommand names (including aliases) and flags, taken from the argument parser, for tab
+/// completion.
+struct CommandTree {
+    name: String,
+    flags: Vec<String>,
+    children: Vec<CommandTree>,
+}
+
+impl CommandTree {
+    /// Builds the tree from the bash completion script generated by the argument parser, since
+    /// clap doesn't otherwise expose subcommands and flags.
+    fn new() -> Self {
+        let mut script = Vec::new();
+        Command::clap().gen_completions_to("vts", Shell::Bash, &mut script);
+        let script = String::from_utf8_lossy(&script);
+
+        // Each command has a case like `vts__hotkeys__trigger)`, followed by a line listing its
+        // flags, positional arguments, and subcommands, like `opts=" -h --name <id> "`
+        let mut opts = HashMap::new();
+        let mut lines = script.lines().map(str::trim);
+        while let Some(line) = lines.next() {
+            let key = match line.strip_suffix(')') {
+                Some(key) if key.starts_with("vts") && !key.contains(char::is_whitespace) => key,
+                _ => continue,
+            };
+
+            if let Some(words) = lines.next().and_then(|line| line.strip_prefix("opts=\"")) {
+                let words = words.trim_end_matches('"').split_whitespace();
+                opts.insert(key, words.collect::<Vec<_>>());
+            }
+        }
+
+        Self::from_opts(&opts, "", "vts")
+    }
+
+    /// Builds the node for a command, given the key of its parent command (e.g., `vts__hotkeys`).
+    fn from_opts(opts: &HashMap<&str, Vec<&str>>, parent: &str, name: &str) -> Self {
+        let key = match parent {
+            "" => name.to_owned(),
+            _ => subcommand_key(parent, name),
+        };
+        let words = opts
+            .get(key.as_str())
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        let subcommands = || {
+            words
+                .iter()
+                .filter(|word| !word.starts_with('-') && !word.starts_with('<'))
+        };
+        let child_count = |key: &str| {
+            subcommands()
+                .filter(|word| opts.contains_key(subcommand_key(key, word).as_str()))
+                .count()
+        };
+
+        // Aliases don't have cases for their own subcommands, so use those of the aliased command
+        // (a sibling with the same options) instead
+        let full_key = opts
+            .iter()
+            .filter(|(other, other_words)| {
+                other.starts_with(parent) && other_words.as_slice() == words
+            })
+            .map(|(other, _)| *other)
+            .max_by_key(|other| (child_count(other), *other == key))
+            .unwrap_or(&key);
+
+        Self {
+            name: name.to_owned(),
+            flags: words
+                .iter()
+                .filter(|word| word.starts_with("--"))
+                .map(|word| word.to_string())
+                .collect(),
+            children: subcommands()
+                .filter(|word| opts.contains_key(subcommand_key(full_key, word).as_str()))
+                .map(|word| Self::from_opts(opts, full_key, word))
+                .collect(),
+        }
+    }
+
+    fn child(&self, name: &str) -> Option<&CommandTree> {
+        self.children.iter().find(|child| child.name == name)
+    }
+}
+
+/// Key of a subcommand in the bash completion script, e.g., `vts__events__model__loaded`.
+fn subcommand_key(parent: &str, name: &str) -> String {
+    format!("{}__{}", parent, name.replace('-', "__"))
+}
+
+struct ShellHelper {
+    commands: CommandTree,
+    names: Mutex<HashMap<NameKind, Vec<String>>>,
+    requests: mpsc::UnboundedSender<NameRequest>,
+}
+
+impl ShellHelper {
+    /// Gets names from the cache, or fetches them from VTube Studio (waiting up to a few seconds).
+    fn names(&self, kind: NameKind) -> Vec<String> {
+        let mut cache = self.names.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(names) = cache.get(&kind) {
+            return names.clone();
+        }
+
+        let (reply, reply_rx) = std_mpsc::channel();
+        if self.requests.send(NameRequest { kind, reply }).is_err() {
+            return Vec::new();
+        }
+
+        match reply_rx.recv_timeout(FETCH_TIMEOUT) {
+            Ok(names) => {
+                cache.insert(kind, names.clone());
+                names
+            }
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+impl Completer for ShellHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &rustyline::Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let before = &line[..pos];
+        let start = before.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let prefix = &before[start..];
+        let words: Vec<&str> = before[..start].split_whitespace().collect();
+
+        let mut node = &self.commands;
+        let mut path = Vec::new();
+        for word in &words {
+            if let Some(child) = node.child(word) {
+                node = child;
+                path.push(child.name.as_str());
+            }
+        }
+
+        let candidates: Vec<String> = match (path.as_slice(), words.last()) {
+            (["models" | "model", "load"], Some(&"--name")) => {
+                quote_all(self.names(NameKind::Models))
+            }
+            (["hotkeys" | "hotkey", "trigger"], Some(&"--name")) => {
+                quote_all(self.names(NameKind::Hotkeys))
+            }
+            _ if prefix.starts_with('-') => node.flags.clone(),
+            ([], _) => node
+                .children
+                .iter()
+                .map(|child| child.name.clone())
+                .chain(["exit".to_owned()])
+                .collect(),
+            _ => node
+                .children
+                .iter()
+                .map(|child| child.name.clone())
+                .collect(),
+        };
+
+        let mut matches: Vec<String> = candidates
+            .into_iter()
+            .filter(|candidate| candidate.starts_with(prefix))
+            .collect();
+        matches.sort();
+        matches.dedup();
+
+        Ok((start, matches))
+    }
+}
+
+/// Quotes names containing whitespace, so they're parsed as a single argument.
+fn quote_all(names: Vec<String>) -> Vec<String> {
+    names
+        .into_iter()
+        .map(|name| {
+            if name.contains(char::is_whitespace) {
+                format!("\"{}\"", name.replace('"', "\\\""))
+            } else {
+                name
+            }
+        })
+        .collect()
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper {}
+
+impl Validator for ShellHelper {}
+
+impl Helper for ShellHelper {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_tree() {
+        let tree = CommandTree::new();
+
+        let trigger = tree.child("hotkeys").unwrap().child("trigger").unwrap();
+        assert!(trigger.flags.contains(&"--name".to_owned()));
+        assert!(trigger.children.is_empty());
+
+        // Aliases and subcommands with dashes in their names
+        assert!(tree.child("hotkey").unwrap().child("trigger").is_some());
+        let events = tree.child("events").unwrap();
+        assert!(events.child("background-changed").is_some());
+        assert!(events.flags.contains(&"--webhook".to_owned()));
+    }
+}